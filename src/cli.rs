@@ -1,11 +1,104 @@
+use crate::discord::{MAX_CONTENT_LENGTH, NITRO_MAX_CONTENT_LENGTH};
+use crate::filter::Filter;
+use crate::rules::{Rule, parse_rule};
+use crate::template::Template;
 use chrono::{DateTime, NaiveDate, Utc};
 use clap::{Parser, ValueEnum};
+use regex::Regex;
+use serde::Deserialize;
 use std::path::PathBuf;
 
-#[derive(Debug, Clone, ValueEnum)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum DeletionMode {
     Delete,
     Shakespeare,
+    RandomWords,
+    Corpus,
+    Text,
+    /// Overwrites the message first (see --overwrite-with), then deletes it
+    OverwriteDelete,
+    /// Only replaces the parts matching --mask-pattern or --mask-pii
+    Mask,
+    /// Replaces every word with a random word of similar length, keeping the message's layout
+    Scramble,
+    /// Replaces the message with an encrypted blob of its content (see --passphrase)
+    Encrypt,
+    /// Restores messages redacted with the `encrypt` mode (needs a newer data export)
+    Restore,
+    /// Removes attachments but keeps the message's text (see --attachment-only)
+    StripAttachments,
+    /// Only hides link previews, keeping text and attachments
+    SuppressEmbeds,
+}
+
+impl DeletionMode {
+    /// Whether this mode edits the message to some generated content.
+    pub fn generates_content(self) -> bool {
+        match self {
+            Self::Shakespeare
+            | Self::RandomWords
+            | Self::Corpus
+            | Self::Text
+            | Self::Mask
+            | Self::Scramble
+            | Self::Encrypt => true,
+            Self::Delete
+            | Self::OverwriteDelete
+            | Self::Restore
+            | Self::StripAttachments
+            | Self::SuppressEmbeds => false,
+        }
+    }
+
+    /// Whether this mode generates different content every time.
+    pub fn is_random(self) -> bool {
+        match self {
+            Self::Shakespeare | Self::RandomWords | Self::Corpus | Self::Scramble => true,
+            Self::Delete
+            | Self::Text
+            | Self::OverwriteDelete
+            | Self::Mask
+            | Self::Encrypt
+            | Self::Restore
+            | Self::StripAttachments
+            | Self::SuppressEmbeds => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum FinalState {
+    /// Leave the content of the last overwrite
+    LastOverwrite,
+    /// Edit the message to --replacement-text
+    Text,
+    /// Delete the message
+    Delete,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum CorpusFormat {
+    /// One replacement text per paragraph (separated by blank lines)
+    Paragraphs,
+    /// Plain prose, split into sentences
+    Sentences,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum AttachmentOnly {
+    /// Delete the message
+    Delete,
+    /// Replace the attachments with --attachment-placeholder
+    Placeholder,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum PiiCategory {
+    Email,
+    Phone,
+    Ip,
+    CreditCard,
 }
 
 /// Discord Selfbot mass message redaction tool
@@ -25,6 +118,19 @@ pub struct Args {
     #[arg(short, long, default_value_t = false)]
     pub preserve_attachments: bool,
 
+    /// Whether to also hide link previews (embeds) when editing messages. Default: false
+    #[arg(long, default_value_t = false)]
+    pub suppress_embeds: bool,
+
+    /// Whether replacement texts may be up to 4000 characters long (needs Discord Nitro). Default: false
+    #[arg(long, default_value_t = false)]
+    pub nitro: bool,
+
+    /// Whether to combine multiple quotes to closely match each message's length
+    /// in the `shakespeare` and `corpus` modes. Default: false
+    #[arg(long, default_value_t = false)]
+    pub match_length: bool,
+
     /// Whether to delete messages from Guilds (Servers). Default: true
     #[arg(long, default_value_t = true)]
     pub delete_guilds: bool,
@@ -64,6 +170,145 @@ pub struct Args {
 
     #[arg(long)]
     pub continuation_file: Option<PathBuf>,
+
+    /// What messages are overwritten with before deleting them in the `overwrite-delete` mode.
+    #[arg(long, value_enum, default_value_t = DeletionMode::Shakespeare)]
+    pub overwrite_with: DeletionMode,
+
+    /// How many times each message is overwritten with different random content.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..=20))]
+    pub passes: u32,
+
+    /// What happens to a message after all overwrite passes.
+    #[arg(long, value_enum, default_value_t = FinalState::LastOverwrite)]
+    pub final_state: FinalState,
+
+    /// A file or directory with replacement texts for the `corpus` mode.
+    #[arg(long, required_if_eq_any([("mode", "corpus"), ("overwrite_with", "corpus")]))]
+    pub corpus: Option<PathBuf>,
+
+    /// How the files given by --corpus are split into replacement texts.
+    #[arg(long, value_enum, default_value_t = CorpusFormat::Paragraphs)]
+    pub corpus_format: CorpusFormat,
+
+    /// The replacement text for the `text` mode, e.g. `redacted on {date}`.
+    /// Placeholders: {date}, {original_date}, {original_length}, {channel}, {message_id}.
+    #[arg(
+        long,
+        required_if_eq_any([("mode", "text"), ("overwrite_with", "text"), ("final_state", "text")]),
+        value_parser = Template::parse,
+    )]
+    pub replacement_text: Option<Template>,
+
+    /// What to do with messages without text in the `strip-attachments` mode,
+    /// since they cannot be edited to be empty.
+    #[arg(long, value_enum, default_value_t = AttachmentOnly::Delete)]
+    pub attachment_only: AttachmentOnly,
+
+    /// The text that attachment-only messages are set to in the `strip-attachments` mode.
+    #[arg(long, default_value = "[attachment removed]")]
+    pub attachment_placeholder: String,
+
+    /// A regex whose matches are replaced in the `mask` mode. Can be given multiple times.
+    #[arg(long, value_parser = parse_regex)]
+    pub mask_pattern: Vec<Regex>,
+
+    /// A comma separated list of personal data categories that are replaced in the `mask` mode.
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub mask_pii: Vec<PiiCategory>,
+
+    /// The text that matched parts are replaced with in the `mask` mode.
+    #[arg(long, default_value = "█████")]
+    pub mask_text: String,
+
+    /// The passphrase used to encrypt messages in the `encrypt` mode and decrypt them in the `restore` mode.
+    #[arg(
+        long,
+        required_if_eq_any([("mode", "encrypt"), ("mode", "restore"), ("overwrite_with", "encrypt")]),
+    )]
+    pub passphrase: Option<String>,
+
+    /// Overrides the mode for some channels, e.g. `dm=delete`, `guild:123=text`
+    /// or `channel:456=shakespeare,preserve-attachments,hide-embeds`. Can be given multiple times.
+    /// Channel rules take precedence over guild rules, which take precedence over channel type rules.
+    #[arg(long, value_parser = parse_rule)]
+    pub rule: Vec<Rule>,
+
+    /// A path to a JSON file with a list of rules (see --rule), e.g.
+    /// `[{"channel_type": "dm", "mode": "delete"}, {"guild": "123", "mode": "text"}]`.
+    #[arg(long)]
+    pub rules_file: Option<PathBuf>,
+
+    /// The base URL of Discord's API, e.g. to use a local test server.
+    #[arg(long, default_value = "https://discord.com/api", value_parser = parse_url)]
+    pub api_base: String,
+
+    /// The version of Discord's API.
+    #[arg(long, default_value_t = 9)]
+    pub api_version: u32,
+
+    /// An HTTP, HTTPS or SOCKS5 proxy for all requests, e.g. `http://proxy:8080` or `socks5://127.0.0.1:1080`.
+    #[arg(long)]
+    pub proxy: Option<String>,
+
+    /// How many seconds to wait for a connection to Discord.
+    #[arg(long, default_value_t = 10.0, value_parser = parse_seconds)]
+    pub connect_timeout: f64,
+
+    /// How many seconds to wait for Discord to respond to a request.
+    #[arg(long, default_value_t = 30.0, value_parser = parse_seconds)]
+    pub timeout: f64,
+
+    /// A PEM file with additional trusted CA certificates, e.g. of a corporate proxy. Can be given multiple times.
+    #[arg(long)]
+    pub ca_cert: Vec<PathBuf>,
+
+    /// Also redact messages sent after the data export was created, found with Discord's search.
+    #[arg(long, default_value_t = false)]
+    pub discover_new: bool,
+
+    /// Start even if the token belongs to a different account than the data export.
+    #[arg(long, default_value_t = false)]
+    pub allow_account_mismatch: bool,
+
+    /// How often a request is retried after server errors or network problems.
+    #[arg(long, default_value_t = 5)]
+    pub max_retries: u32,
+
+    /// The delay in seconds before the first retry, doubled for every further retry.
+    #[arg(long, default_value_t = 1.0, value_parser = parse_seconds)]
+    pub retry_delay: f64,
+
+    /// The maximum delay in seconds between two retries.
+    #[arg(long, default_value_t = 60.0, value_parser = parse_seconds)]
+    pub max_retry_delay: f64,
+
+    /// How many channels are redacted at the same time.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..=16))]
+    pub workers: u32,
+
+    /// Makes generated replacement texts depend only on this seed and the message,
+    /// so reruns produce the same content. Encrypted blobs stay random.
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Only delete messages matching this expression,
+    /// e.g. `guild == 123 && year < 2022 && !has_attachments && content ~ "password"`.
+    /// Fields: id, content, attachments, has_attachments, length, year, month, day, hour, date,
+    /// channel, channel_name, channel_type, guild, guild_name, is_guild, is_dm, is_group.
+    #[arg(long, value_parser = Filter::parse)]
+    pub filter: Option<Filter>,
+}
+
+impl Args {
+    /// The maximum amount of characters a message may be edited to.
+    pub fn content_limit(&self) -> usize {
+        if self.nitro {
+            NITRO_MAX_CONTENT_LENGTH
+        } else {
+            MAX_CONTENT_LENGTH
+        }
+    }
 }
 
 fn parse_date(s: &str) -> Result<DateTime<Utc>, String> {
@@ -71,3 +316,19 @@ fn parse_date(s: &str) -> Result<DateTime<Utc>, String> {
         .map(|date| date.and_hms_opt(0, 0, 0).unwrap().and_utc())
         .map_err(|e| format!("Invalid date: '{}'. Use YYYY-MM-DD format: {}", s, e))
 }
+
+fn parse_seconds(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(seconds) if seconds.is_finite() && seconds >= 0.0 => Ok(seconds),
+        _ => Err(format!("Invalid amount of seconds: {s:?}")),
+    }
+}
+
+fn parse_url(s: &str) -> Result<String, String> {
+    url::Url::parse(s).map_err(|e| format!("Invalid URL {s:?}: {e}"))?;
+    Ok(s.to_string())
+}
+
+fn parse_regex(s: &str) -> Result<Regex, String> {
+    Regex::new(s).map_err(|e| format!("Invalid regex {s:?}: {e}"))
+}
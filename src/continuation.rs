@@ -1,37 +1,80 @@
+use std::collections::HashMap;
 use std::io::Write;
+use std::sync::Mutex;
 use std::{collections::HashSet, fs::OpenOptions, path::Path};
 
-pub fn parse_continuation_file(path: &Path) -> Result<HashSet<u64>, String> {
+/// Keeps lines written by different workers from interleaving.
+static WRITE_LOCK: Mutex<()> = Mutex::new(());
+
+/// The state of a previous run, read from the continuation file.
+///
+/// Every line contains either a message ID on its own for finished messages,
+/// or a message ID followed by the number of completed steps for messages
+/// that need several requests (e.g. overwriting and then deleting).
+#[derive(Debug, Default)]
+pub struct Continuation {
+    pub finished: HashSet<u64>,
+    pub completed_steps: HashMap<u64, usize>,
+}
+
+pub fn parse_continuation_file(path: &Path) -> Result<Continuation, String> {
     let content = std::fs::read_to_string(path)
         .map_err(|e| format!("Could not read continuation file: {e}"))?;
 
-    let mut ids = HashSet::new();
+    let mut continuation = Continuation::default();
 
     for line in content.lines() {
         let line = line.trim();
         if line.is_empty() {
             continue;
         }
-        let id: u64 = line
+        let (id, steps) = match line.split_once(' ') {
+            Some((id, steps)) => (id, Some(steps.trim())),
+            None => (line, None),
+        };
+        let id: u64 = id
             .parse()
             .map_err(|e| format!("Invalid Message ID {line:?} in continuation file: {e}"))?;
-        ids.insert(id);
+        match steps {
+            Some(steps) => {
+                let steps: usize = steps.parse().map_err(|e| {
+                    format!("Invalid step count {line:?} in continuation file: {e}")
+                })?;
+                let entry = continuation.completed_steps.entry(id).or_default();
+                *entry = (*entry).max(steps);
+            }
+            None => {
+                continuation.finished.insert(id);
+            }
+        }
     }
 
-    Ok(ids)
+    Ok(continuation)
 }
 
-/// Appends a message ID to the continuation file.
-/// Errors are logged rather than returned since continuation file failures
-/// should not halt processing.
-pub fn write_continuation_file(path: &Path, message_id: u64) {
+fn append_line(path: &Path, line: std::fmt::Arguments) {
+    let _guard = WRITE_LOCK
+        .lock()
+        .expect("Continuation file lock should not be poisoned");
     let result = OpenOptions::new()
         .append(true)
         .create(true)
         .open(path)
-        .and_then(|mut f| writeln!(f, "{}", message_id));
+        .and_then(|mut f| f.write_all(format!("{line}\n").as_bytes()));
 
     if let Err(e) = result {
         eprintln!("Failed to write to continuation file: {e}");
     }
 }
+
+/// Appends a message ID to the continuation file.
+/// Errors are logged rather than returned since continuation file failures
+/// should not halt processing.
+pub fn write_continuation_file(path: &Path, message_id: u64) {
+    append_line(path, format_args!("{message_id}"));
+}
+
+/// Records that the first `completed_steps` steps of a multi-step redaction are done.
+pub fn write_continuation_step(path: &Path, message_id: u64, completed_steps: usize) {
+    append_line(path, format_args!("{message_id} {completed_steps}"));
+}
@@ -0,0 +1,208 @@
+use crate::cli::CorpusFormat;
+use crate::discord::NITRO_MAX_CONTENT_LENGTH;
+use rand::Rng;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// The granularity of the length index.
+const LENGTH_STEP: usize = 10;
+/// How many entries to choose from at least, even if fewer have a fitting length.
+const MIN_CHOICES: usize = 10;
+/// Messages shorter than this do not get another quote appended in `--match-length` mode.
+const MIN_FILL_LENGTH: usize = 20;
+
+/// Replacement texts indexed by length (in characters), either from the bundled
+/// Shakespeare quotes or loaded from a user-provided file or directory.
+#[derive(Debug)]
+pub struct Corpus {
+    /// Sorted by length, shortest first.
+    entries: Vec<String>,
+    /// `entry_counts[i]` is the amount of entries with at most `i * LENGTH_STEP` characters.
+    entry_counts: Vec<usize>,
+}
+
+fn split_paragraphs(text: &str) -> Vec<String> {
+    text.replace("\r\n", "\n")
+        .split("\n\n")
+        .map(|paragraph| paragraph.trim().to_string())
+        .filter(|paragraph| !paragraph.is_empty())
+        .collect()
+}
+
+fn split_sentences(text: &str) -> Vec<String> {
+    let mut sentences = vec![];
+    let mut current = String::new();
+
+    for word in text.split_whitespace() {
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(word);
+        if word.ends_with(['.', '!', '?']) {
+            sentences.push(std::mem::take(&mut current));
+        }
+    }
+    if !current.is_empty() {
+        sentences.push(current);
+    }
+    sentences
+}
+
+fn collect_files(path: &Path) -> Result<Vec<PathBuf>, String> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    let mut files = vec![];
+    let entries = path
+        .read_dir()
+        .map_err(|e| format!("Could not get children of corpus directory {path:?}: {e}"))?;
+    for entry in entries {
+        let entry = entry.map_err(|e| format!("Could not get child of directory: {e}"))?;
+        let path = entry.path();
+        if path.is_dir() {
+            files.extend(collect_files(&path)?);
+        } else {
+            files.push(path);
+        }
+    }
+    // Keep the order independent of the file system.
+    files.sort();
+    Ok(files)
+}
+
+impl Corpus {
+    /// Builds the length index. Entries that do not fit into a message are dropped.
+    pub fn from_entries(mut entries: Vec<String>) -> Result<Self, String> {
+        entries.retain(|entry| entry.chars().count() <= NITRO_MAX_CONTENT_LENGTH);
+        entries.sort_by_cached_key(|entry| entry.chars().count());
+
+        let shortest = MIN_CHOICES * LENGTH_STEP;
+        if entries
+            .first()
+            .is_none_or(|entry| entry.chars().count() > shortest)
+        {
+            return Err(format!(
+                "At least one entry with at most {shortest} characters is needed"
+            ));
+        }
+
+        let lengths: Vec<usize> = entries.iter().map(|entry| entry.chars().count()).collect();
+        let entry_counts = (0..=NITRO_MAX_CONTENT_LENGTH / LENGTH_STEP)
+            .map(|i| lengths.partition_point(|&length| length <= i * LENGTH_STEP))
+            .collect();
+        Ok(Self {
+            entries,
+            entry_counts,
+        })
+    }
+
+    pub fn load(path: &Path, format: CorpusFormat) -> Result<Self, String> {
+        let mut entries = vec![];
+
+        for file in collect_files(path)? {
+            let text = std::fs::read_to_string(&file)
+                .map_err(|e| format!("Could not read corpus file {file:?}: {e}"))?;
+            match format {
+                CorpusFormat::Paragraphs => entries.extend(split_paragraphs(&text)),
+                CorpusFormat::Sentences => entries.extend(split_sentences(&text)),
+            }
+        }
+
+        let too_long = entries
+            .iter()
+            .filter(|entry| entry.chars().count() > NITRO_MAX_CONTENT_LENGTH)
+            .count();
+        if too_long > 0 {
+            println!(
+                "Ignoring {too_long} corpus entries longer than {NITRO_MAX_CONTENT_LENGTH} characters."
+            );
+        }
+
+        Self::from_entries(entries).map_err(|e| format!("Invalid corpus {path:?}: {e}"))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// The amount of entries with at most (roughly) the given length.
+    fn count_up_to(&self, length: usize) -> usize {
+        let index = (length / LENGTH_STEP).min(self.entry_counts.len() - 1);
+        self.entry_counts[index]
+    }
+
+    /// Picks a random entry of similar length that is at most `max_length` long.
+    /// Entries whose indices are in `used` are avoided until all fitting entries were used.
+    fn pick(
+        &self,
+        rng: &mut impl Rng,
+        length: usize,
+        max_length: usize,
+        used: &mut HashSet<usize>,
+    ) -> &str {
+        // Always have at least the shortest entries to choose from.
+        let end = self
+            .count_up_to(max_length)
+            .max(MIN_CHOICES.min(self.entries.len()));
+        let begin = self
+            .count_up_to(length / 2)
+            .min(end.saturating_sub(MIN_CHOICES));
+
+        let start = rng.random_range(begin..end);
+        let index = (start..end)
+            .chain(begin..start)
+            .find(|index| !used.contains(index));
+
+        let index = match index {
+            Some(index) => index,
+            None => {
+                // Every fitting entry was used already; start over.
+                used.retain(|&index| !(begin..end).contains(&index));
+                start
+            }
+        };
+        used.insert(index);
+        &self.entries[index]
+    }
+
+    /// Generates a replacement text for a message with the given amount of characters.
+    ///
+    /// Normally, a single entry of similar length is picked.
+    /// With `match_length`, entries are combined to get as close to the length as possible.
+    pub fn generate(
+        &self,
+        rng: &mut impl Rng,
+        length: usize,
+        content_limit: usize,
+        match_length: bool,
+        used: &mut HashSet<usize>,
+    ) -> String {
+        let length = length.clamp(1, content_limit);
+        if !match_length {
+            let max_length = (length + length / 2).min(content_limit);
+            return self.pick(rng, length, max_length, used).to_string();
+        }
+
+        let mut result = self.pick(rng, length, length, used).to_string();
+        let mut current = result.chars().count();
+        // Paragraph break between two entries
+        let separator = 2;
+
+        while current + separator + MIN_FILL_LENGTH <= length {
+            let remaining = length - current - separator;
+            if self.count_up_to(remaining) == 0 {
+                break;
+            }
+            let entry = self.pick(rng, remaining, remaining, used);
+            let entry_length = entry.chars().count();
+            if entry_length > remaining {
+                break;
+            }
+            result.push_str("\n\n");
+            result.push_str(entry);
+            current += separator + entry_length;
+        }
+        result
+    }
+}
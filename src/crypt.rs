@@ -0,0 +1,113 @@
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use argon2::Argon2;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use rand::Rng;
+
+/// Marks a message content as an encrypted blob created by this tool.
+const PREFIX: &str = "dmr1:";
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 12;
+const TAG_LENGTH: usize = 16;
+
+/// The longest content (in bytes) whose encrypted blob still fits into a message.
+pub fn max_plaintext_length(content_limit: usize) -> usize {
+    (content_limit - PREFIX.len()) / 4 * 3 - SALT_LENGTH - NONCE_LENGTH - TAG_LENGTH
+}
+
+fn derive_cipher(passphrase: &str, salt: &[u8]) -> Result<Aes256Gcm, String> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| format!("Could not derive key from passphrase: {e}"))?;
+    Ok(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)))
+}
+
+/// Encrypts the content with AES-256-GCM, using a key derived from the passphrase
+/// with Argon2 and a random salt, and encodes everything as a base64 blob.
+pub fn encrypt(passphrase: &str, content: &str, content_limit: usize) -> Result<String, String> {
+    let max_length = max_plaintext_length(content_limit);
+    if content.len() > max_length {
+        return Err(format!(
+            "Message is {} bytes long, but at most {max_length} bytes can be encrypted",
+            content.len(),
+        ));
+    }
+
+    let mut salt = [0u8; SALT_LENGTH];
+    let mut nonce = [0u8; NONCE_LENGTH];
+    rand::rng().fill(&mut salt);
+    rand::rng().fill(&mut nonce);
+
+    let cipher = derive_cipher(passphrase, &salt)?;
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), content.as_bytes())
+        .map_err(|e| format!("Could not encrypt message: {e}"))?;
+
+    let mut blob = Vec::with_capacity(SALT_LENGTH + NONCE_LENGTH + ciphertext.len());
+    blob.extend_from_slice(&salt);
+    blob.extend_from_slice(&nonce);
+    blob.extend_from_slice(&ciphertext);
+    Ok(format!("{PREFIX}{}", BASE64.encode(blob)))
+}
+
+/// Whether the content looks like a blob created by [`encrypt`].
+pub fn is_encrypted(content: &str) -> bool {
+    content.starts_with(PREFIX)
+}
+
+pub fn decrypt(passphrase: &str, content: &str) -> Result<String, String> {
+    let encoded = content
+        .strip_prefix(PREFIX)
+        .ok_or("Message is not encrypted")?;
+    let blob = BASE64
+        .decode(encoded.trim())
+        .map_err(|e| format!("Encrypted message is not valid base64: {e}"))?;
+    if blob.len() < SALT_LENGTH + NONCE_LENGTH + TAG_LENGTH {
+        return Err("Encrypted message is too short".to_string());
+    }
+
+    let (salt, rest) = blob.split_at(SALT_LENGTH);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LENGTH);
+    let cipher = derive_cipher(passphrase, salt)?;
+    let plaintext = cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| "Could not decrypt message (wrong passphrase?)".to_string())?;
+    String::from_utf8(plaintext).map_err(|e| format!("Decrypted message is not UTF-8: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::discord::MAX_CONTENT_LENGTH;
+
+    #[test]
+    fn decrypts_what_it_encrypted() {
+        let content = "meet me at 5 ✨\nsecond line";
+        let blob = encrypt("hunter2", content, MAX_CONTENT_LENGTH).unwrap();
+        assert!(is_encrypted(&blob));
+        assert!(!blob.contains("meet"));
+        assert_eq!(decrypt("hunter2", &blob).unwrap(), content);
+    }
+
+    #[test]
+    fn rejects_wrong_passphrases() {
+        let blob = encrypt("hunter2", "secret", MAX_CONTENT_LENGTH).unwrap();
+        assert_eq!(
+            decrypt("hunter3", &blob).unwrap_err(),
+            "Could not decrypt message (wrong passphrase?)"
+        );
+    }
+
+    #[test]
+    fn longest_plaintext_fits_into_a_message() {
+        let length = max_plaintext_length(MAX_CONTENT_LENGTH);
+        let content = "a".repeat(length);
+        let blob = encrypt("hunter2", &content, MAX_CONTENT_LENGTH).unwrap();
+        assert!(blob.chars().count() <= MAX_CONTENT_LENGTH, "{}", blob.len());
+
+        let too_long = "a".repeat(length + 1);
+        assert!(encrypt("hunter2", &too_long, MAX_CONTENT_LENGTH).is_err());
+    }
+}
@@ -1,72 +1,361 @@
 use crate::CLIENT;
+use crate::ratelimit::{self, Route};
+use chrono::{DateTime, Utc};
 use colored::Colorize;
+use indicatif::ProgressBar;
 use reqwest::StatusCode;
-use reqwest::blocking::Response;
+use reqwest::blocking::{RequestBuilder, Response};
+use serde::Deserialize;
 use serde_json::{Value, json};
+use serde_with::{DisplayFromStr, serde_as};
+use std::fmt;
 use std::str::FromStr;
+use std::sync::OnceLock;
 use std::thread::sleep;
 use std::time::Duration;
 use url::Url;
 
 pub enum DiscordError {
     RateLimited(f64),
+    /// Discord rejected the request.
+    Api {
+        status: StatusCode,
+        code: Option<ErrorCode>,
+        message: String,
+    },
+    /// The request could not be sent or the response could not be read, e.g. due to a timeout.
+    Transport(String),
+    /// A server or network error persisted after retrying with backoff.
+    RetriesExhausted {
+        attempts: u32,
+        last: Box<DiscordError>,
+    },
     Other(String),
 }
 
+impl DiscordError {
+    /// Whether the error may go away when sending the same request again later.
+    fn is_transient(&self) -> bool {
+        match self {
+            Self::Transport(_) => true,
+            Self::Api { status, .. } => status.is_server_error(),
+            _ => false,
+        }
+    }
+
+    pub fn code(&self) -> Option<ErrorCode> {
+        match self {
+            Self::Api { code, .. } => *code,
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for DiscordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::RateLimited(retry_after) => write!(f, "Rate limited for {retry_after:.2}s"),
+            Self::Api {
+                status,
+                code,
+                message,
+            } => {
+                write!(
+                    f,
+                    "Discord responded with status code {} {}: {message:?}",
+                    status.as_u16(),
+                    status.canonical_reason().unwrap_or("<unknown status>"),
+                )?;
+                if let Some(code) = code {
+                    write!(f, " (code {})", code.value())?;
+                }
+                Ok(())
+            }
+            Self::Transport(message) => f.write_str(message),
+            Self::RetriesExhausted { attempts, last } => {
+                write!(f, "Gave up after {attempts} attempts: {last}")
+            }
+            Self::Other(message) => f.write_str(message),
+        }
+    }
+}
+
+/// The JSON error codes from <https://discord.com/developers/docs/topics/opcodes-and-status-codes#json>
+/// that matter for redacting messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    UnknownChannel,
+    UnknownMessage,
+    Unauthorized,
+    MissingAccess,
+    /// Cannot edit a message authored by another user
+    NotAuthor,
+    /// Cannot send an empty message
+    EmptyMessage,
+    MissingPermissions,
+    /// Cannot execute action on a system message
+    SystemMessage,
+    InvalidFormBody,
+    /// Thread is archived
+    ArchivedThread,
+    ThreadLocked,
+    Other(u64),
+}
+
+impl ErrorCode {
+    /// Whether this error applies to every message in the channel, not just the current one.
+    pub fn is_channel_wide(self) -> bool {
+        matches!(
+            self,
+            Self::UnknownChannel | Self::MissingAccess | Self::ArchivedThread | Self::ThreadLocked
+        )
+    }
+
+    pub fn value(self) -> u64 {
+        match self {
+            Self::UnknownChannel => 10003,
+            Self::UnknownMessage => 10008,
+            Self::Unauthorized => 40001,
+            Self::MissingAccess => 50001,
+            Self::NotAuthor => 50005,
+            Self::EmptyMessage => 50006,
+            Self::MissingPermissions => 50013,
+            Self::SystemMessage => 50021,
+            Self::InvalidFormBody => 50035,
+            Self::ArchivedThread => 50083,
+            Self::ThreadLocked => 160005,
+            Self::Other(code) => code,
+        }
+    }
+}
+
+impl From<u64> for ErrorCode {
+    fn from(code: u64) -> Self {
+        match code {
+            10003 => Self::UnknownChannel,
+            10008 => Self::UnknownMessage,
+            40001 => Self::Unauthorized,
+            50001 => Self::MissingAccess,
+            50005 => Self::NotAuthor,
+            50006 => Self::EmptyMessage,
+            50013 => Self::MissingPermissions,
+            50021 => Self::SystemMessage,
+            50035 => Self::InvalidFormBody,
+            50083 => Self::ArchivedThread,
+            160005 => Self::ThreadLocked,
+            code => Self::Other(code),
+        }
+    }
+}
+
 impl From<String> for DiscordError {
     fn from(value: String) -> Self {
         Self::Other(value)
     }
 }
 
-const API_PREFIX: &str = "https://discord.com/api/v9";
+/// The maximum amount of characters in a message's content.
+pub const MAX_CONTENT_LENGTH: usize = 2000;
+/// The maximum amount of characters in a message's content for users with Nitro.
+pub const NITRO_MAX_CONTENT_LENGTH: usize = 4000;
+
+/// Hides all link previews of a message.
+const SUPPRESS_EMBEDS_FLAG: u64 = 1 << 2;
+
+static API_PREFIX: OnceLock<String> = OnceLock::new();
+
+/// Sets the API base URL and version for all requests. Must be called before the first request.
+pub fn set_api_prefix(base_url: &str, version: u32) {
+    let prefix = format!("{}/v{version}", base_url.trim_end_matches('/'));
+    if API_PREFIX.set(prefix).is_err() {
+        panic!("The API prefix was already set");
+    }
+}
+
+fn api_prefix() -> &'static str {
+    API_PREFIX.get_or_init(|| "https://discord.com/api/v9".to_string())
+}
 
 fn get_url(channel_id: u64, message_id: u64) -> Result<Url, String> {
-    let url: String = format!("{API_PREFIX}/channels/{channel_id}/messages/{message_id}");
+    let url: String = format!(
+        "{}/channels/{channel_id}/messages/{message_id}",
+        api_prefix()
+    );
     Url::from_str(&url).map_err(|e| format!("Could not deserialize URL {url:?}: {e}"))
 }
 
-fn handle_response(response: Response) -> Result<Value, DiscordError> {
+const MESSAGE_ROUTE_PATCH: &str = "PATCH /channels/{channel_id}/messages/{message_id}";
+const MESSAGE_ROUTE_DELETE: &str = "DELETE /channels/{channel_id}/messages/{message_id}";
+const PROFILE_ROUTE: &str = "GET /users/{user_id}/profile";
+const CURRENT_USER_ROUTE: &str = "GET /users/@me";
+const GUILD_SEARCH_ROUTE: &str = "GET /guilds/{guild_id}/messages/search";
+const CHANNEL_SEARCH_ROUTE: &str = "GET /channels/{channel_id}/messages/search";
+/// How often a search is sent while Discord is still indexing the messages.
+const MAX_SEARCH_ATTEMPTS: u32 = 10;
+
+/// How often and how long to retry after server errors and network problems.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    /// The delay before the first retry in seconds, doubled for every further retry.
+    pub initial_delay: f64,
+    pub max_delay: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 5,
+            initial_delay: 1.0,
+            max_delay: 60.0,
+        }
+    }
+}
+
+static RETRY_POLICY: OnceLock<RetryPolicy> = OnceLock::new();
+
+/// Sets the retry policy for all requests. Must be called before the first request.
+pub fn set_retry_policy(policy: RetryPolicy) {
+    if RETRY_POLICY.set(policy).is_err() {
+        panic!("The retry policy was already set");
+    }
+}
+
+static PROGRESS_BAR: OnceLock<ProgressBar> = OnceLock::new();
+
+/// Prints notices about requests above the progress bar instead of between its redraws.
+pub fn set_progress_bar(bar: ProgressBar) {
+    if PROGRESS_BAR.set(bar).is_err() {
+        panic!("The progress bar was already set");
+    }
+}
+
+fn print_notice(notice: String) {
+    match PROGRESS_BAR.get() {
+        Some(bar) => bar.println(notice),
+        None => println!("{notice}"),
+    }
+}
+
+/// Sends a request once the route's rate limit allows it,
+/// retrying with exponential backoff after server errors and network problems.
+/// Returns the status code, e.g. to tell 202 Accepted apart, and the JSON body.
+fn send(
+    route: &Route,
+    request: impl Fn() -> RequestBuilder,
+) -> Result<(StatusCode, Value), DiscordError> {
+    let policy = RETRY_POLICY.get_or_init(RetryPolicy::default);
+    let mut retries = 0;
+    loop {
+        match send_once(route, request()) {
+            Err(error) if error.is_transient() => {
+                if retries >= policy.max_retries {
+                    return Err(DiscordError::RetriesExhausted {
+                        attempts: retries + 1,
+                        last: Box::new(error),
+                    });
+                }
+                let delay =
+                    (policy.initial_delay * 2f64.powi(retries as i32)).min(policy.max_delay);
+                print_notice(
+                    format!("{error}. Retrying in {delay:.1}s")
+                        .cyan()
+                        .to_string(),
+                );
+                sleep(Duration::from_secs_f64(delay));
+                retries += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Like [`send`], but waits out rate limits instead of returning them,
+/// for requests that are not tied to a message the caller could reschedule.
+fn send_waiting(
+    route: &Route,
+    request: impl Fn() -> RequestBuilder,
+) -> Result<(StatusCode, Value), DiscordError> {
+    loop {
+        match send(route, &request) {
+            Err(DiscordError::RateLimited(retry_after)) => {
+                sleep(Duration::from_secs_f64(retry_after));
+            }
+            result => return result,
+        }
+    }
+}
+
+fn send_once(route: &Route, request: RequestBuilder) -> Result<(StatusCode, Value), DiscordError> {
+    ratelimit::wait(route);
+    let response: Response = request
+        .send()
+        .map_err(|e| DiscordError::Transport(format!("Failed to send request: {e}")))?;
+    let retry_after = ratelimit::update(
+        route,
+        response.headers(),
+        response.status() == StatusCode::TOO_MANY_REQUESTS,
+    );
+    handle_response(response, retry_after)
+}
+
+fn handle_response(
+    response: Response,
+    retry_after: Option<f64>,
+) -> Result<(StatusCode, Value), DiscordError> {
     let status: StatusCode = response.status();
 
     let text: String = response
         .text()
-        .map_err(|e| format!("Failed to read response text: {e}"))?;
+        .map_err(|e| DiscordError::Transport(format!("Failed to read response text: {e}")))?;
 
-    let json: Value = serde_json::from_str(&text)
-        .map_err(|e| format!("Failed to parse JSON: {e}\nResponse body: {text}"))?;
+    // Deleting a message responds with 204 No Content.
+    if status.is_success() && text.trim().is_empty() {
+        return Ok((status, Value::Null));
+    }
+
+    let json: Value = match serde_json::from_str(&text) {
+        Ok(json) => json,
+        // Error pages of proxies or Discord's edge are not always JSON.
+        Err(_) if !status.is_success() => Value::String(text),
+        Err(e) => return Err(format!("Failed to parse JSON: {e}\nResponse body: {text}").into()),
+    };
 
     if status.is_success() {
-        return Ok(json);
+        return Ok((status, json));
     }
 
     if status == StatusCode::TOO_MANY_REQUESTS {
-        let retry_after: f64 = extract_retry_after(json);
-        return Err(DiscordError::RateLimited(retry_after + 0.1));
+        let retry_after: f64 = extract_retry_after(json, retry_after);
+        return Err(DiscordError::RateLimited(retry_after));
     }
 
     let message = match json.get("message").and_then(|v| v.as_str()) {
         Some(msg) => msg.to_string(),
         None => json.to_string(),
     };
+    let code = json
+        .get("code")
+        .and_then(|v| v.as_u64())
+        .map(ErrorCode::from);
 
-    Err(format!(
-        "Discord responded with status code {} {}: {:?}",
-        status.as_u16(),
-        status.canonical_reason().unwrap_or("<unknown status>"),
+    Err(DiscordError::Api {
+        status,
+        code,
         message,
-    )
-    .into())
+    })
 }
 
-fn extract_retry_after(json: Value) -> f64 {
+/// Prefers the precise `retry_after` field of the body over the rate limit headers.
+fn extract_retry_after(json: Value, from_headers: Option<f64>) -> f64 {
     json.get("retry_after")
         .and_then(|v| v.as_f64())
+        .or(from_headers)
         .unwrap_or_else(|| {
-            println!(
+            print_notice(format!(
                 "{} Json: {json}",
                 "Discord did not provide a `retry_after` field. Defaulting to 1 second.".cyan()
-            );
+            ));
             1.0
         })
 }
@@ -75,69 +364,186 @@ pub fn edit_message(
     token: &str,
     channel_id: u64,
     message_id: u64,
-    content: &str,
+    content: Option<&str>,
     preserve_attachments: bool,
+    suppress_embeds: bool,
 ) -> Result<(), DiscordError> {
     let url = get_url(channel_id, message_id)?;
 
-    let json = if preserve_attachments {
-        json!({ "content": content })
-    } else {
-        json!({ "content": content, "attachments": [] })
-    };
+    // Fields that are left out stay unchanged.
+    let mut json = json!({});
+    if let Some(content) = content {
+        json["content"] = json!(content);
+    }
+    if !preserve_attachments {
+        json["attachments"] = json!([]);
+    }
+    if suppress_embeds {
+        json["flags"] = json!(SUPPRESS_EMBEDS_FLAG);
+    }
 
-    let response: Response = CLIENT
-        .patch(url)
-        .header("Authorization", token)
-        .json(&json)
-        .send()
-        .map_err(|e| format!("Failed to send request: {e}"))?;
+    let route = Route {
+        name: MESSAGE_ROUTE_PATCH,
+        major_parameter: Some(channel_id),
+    };
+    let request = || {
+        CLIENT
+            .patch(url.clone())
+            .header("Authorization", token)
+            .json(&json)
+    };
 
-    handle_response(response)?;
+    send(&route, request)?;
     Ok(())
 }
 
 pub fn delete_message(token: &str, channel_id: u64, message_id: u64) -> Result<(), DiscordError> {
     let url = get_url(channel_id, message_id)?;
 
-    let response: Response = CLIENT
-        .delete(url)
-        .header("Authorization", token)
-        .send()
-        .map_err(|e| format!("Failed to send request: {e}"))?;
+    let route = Route {
+        name: MESSAGE_ROUTE_DELETE,
+        major_parameter: Some(channel_id),
+    };
+    let request = || CLIENT.delete(url.clone()).header("Authorization", token);
 
-    handle_response(response)?;
+    send(&route, request)?;
     Ok(())
 }
 
 pub fn user_get_displayname(token: &str, user_id: u64) -> Result<String, String> {
-    let url = format!("{API_PREFIX}/users/{user_id}/profile");
+    let url = format!("{}/users/{user_id}/profile", api_prefix());
     let url = Url::from_str(&url).map_err(|e| format!("Could not deserialize URL {url:?}: {e}"))?;
 
-    loop {
-        let response: Response = CLIENT
-            .get(url.clone())
-            .header("Authorization", token)
-            .send()
-            .map_err(|e| format!("Failed to send request: {e}"))?;
-
-        match handle_response(response) {
-            Ok(json) => {
-                let display_name = json
-                    .get("user")
-                    .and_then(|i| i.get("global_name"))
-                    .and_then(|i| i.as_str())
-                    .ok_or_else(|| {
-                        format!("Json does not contain string user.global_name: {json}")
-                    })?;
-                return Ok(display_name.to_string());
-            }
-            Err(DiscordError::RateLimited(retry_after)) => {
-                sleep(Duration::from_secs_f64(retry_after));
-            }
-            Err(DiscordError::Other(e)) => {
-                return Err(e);
-            }
+    let route = Route {
+        name: PROFILE_ROUTE,
+        major_parameter: None,
+    };
+    let request = || CLIENT.get(url.clone()).header("Authorization", token);
+
+    let (_, json) = send_waiting(&route, request).map_err(|error| error.to_string())?;
+    let display_name = json
+        .get("user")
+        .and_then(|i| i.get("global_name"))
+        .and_then(|i| i.as_str())
+        .ok_or_else(|| format!("Json does not contain string user.global_name: {json}"))?;
+    Ok(display_name.to_string())
+}
+
+/// The account the token belongs to.
+#[serde_as]
+#[derive(Debug, Deserialize)]
+pub struct CurrentUser {
+    #[serde_as(as = "DisplayFromStr")]
+    pub id: u64,
+    pub username: String,
+}
+
+pub fn get_current_user(token: &str) -> Result<CurrentUser, DiscordError> {
+    let url = format!("{}/users/@me", api_prefix());
+    let url = Url::from_str(&url).map_err(|e| format!("Could not deserialize URL {url:?}: {e}"))?;
+
+    let route = Route {
+        name: CURRENT_USER_ROUTE,
+        major_parameter: None,
+    };
+    let request = || CLIENT.get(url.clone()).header("Authorization", token);
+
+    let (_, json) = send_waiting(&route, request)?;
+    serde_json::from_value(json)
+        .map_err(|e| format!("Invalid user object from Discord: {e}").into())
+}
+
+/// Where to search for messages.
+#[derive(Debug, Clone, Copy)]
+pub enum SearchScope {
+    Guild(u64),
+    /// A DM or group DM.
+    Channel(u64),
+}
+
+#[serde_as]
+#[derive(Debug, Deserialize)]
+pub struct FoundMessage {
+    #[serde_as(as = "DisplayFromStr")]
+    pub id: u64,
+    #[serde_as(as = "DisplayFromStr")]
+    pub channel_id: u64,
+    pub content: String,
+    pub timestamp: DateTime<Utc>,
+    pub attachments: Vec<FoundAttachment>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FoundAttachment {
+    pub url: String,
+}
+
+/// Searches for messages by the given author that are newer than `min_id`, oldest first.
+/// Returns one page of results; an empty page means there are no more messages.
+pub fn search_messages(
+    token: &str,
+    scope: SearchScope,
+    author_id: u64,
+    min_id: u64,
+) -> Result<Vec<FoundMessage>, DiscordError> {
+    let (url, route) = match scope {
+        SearchScope::Guild(guild_id) => (
+            format!("{}/guilds/{guild_id}/messages/search", api_prefix()),
+            Route {
+                name: GUILD_SEARCH_ROUTE,
+                major_parameter: Some(guild_id),
+            },
+        ),
+        SearchScope::Channel(channel_id) => (
+            format!("{}/channels/{channel_id}/messages/search", api_prefix()),
+            Route {
+                name: CHANNEL_SEARCH_ROUTE,
+                major_parameter: Some(channel_id),
+            },
+        ),
+    };
+    let url = Url::parse_with_params(
+        &url,
+        [
+            ("author_id", author_id.to_string()),
+            ("min_id", min_id.to_string()),
+            ("sort_by", "timestamp".to_string()),
+            ("sort_order", "asc".to_string()),
+            ("include_nsfw", "true".to_string()),
+        ],
+    )
+    .map_err(|e| format!("Could not deserialize URL {url:?}: {e}"))?;
+
+    let request = || CLIENT.get(url.clone()).header("Authorization", token);
+    for _ in 0..MAX_SEARCH_ATTEMPTS {
+        let (status, json) = send_waiting(&route, request)?;
+
+        // While Discord is still indexing, it responds with 202 Accepted and a `retry_after`.
+        if status == StatusCode::ACCEPTED {
+            let retry_after = extract_retry_after(json, None);
+            sleep(Duration::from_secs_f64(retry_after));
+            continue;
         }
+        let Some(groups) = json.get("messages").and_then(|v| v.as_array()) else {
+            return Err(format!("Search response does not contain messages: {json}").into());
+        };
+
+        // Every result is a group of messages, the hit itself and possibly some context.
+        return groups
+            .iter()
+            .filter_map(|group| {
+                let group = group.as_array()?;
+                group
+                    .iter()
+                    .find(|m| m.get("hit").and_then(|v| v.as_bool()) == Some(true))
+                    .or_else(|| group.first())
+            })
+            .map(|message| {
+                serde_json::from_value(message.clone()).map_err(|e| {
+                    format!("Invalid message from search: {e}\nMessage: {message}").into()
+                })
+            })
+            .collect();
     }
+    Err(format!("Discord was still indexing after {MAX_SEARCH_ATTEMPTS} attempts").into())
 }
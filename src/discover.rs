@@ -0,0 +1,129 @@
+use crate::Args;
+use crate::discord::{FoundMessage, SearchScope, search_messages};
+use crate::extract::{
+    Channel, Guild, Message, is_blacklisted, is_channel_selected, is_message_selected,
+    newest_message_id,
+};
+use colored::Colorize;
+use std::collections::{BTreeMap, HashSet};
+
+/// Uses Discord's search to find messages the user sent after the data export was created,
+/// and adds them to the channels from the export (or new channels in the same guilds).
+/// The same options and filters apply as for messages from the export.
+pub fn discover_messages(
+    args: &Args,
+    user_id: u64,
+    channels: &mut Vec<(Channel, Vec<Message>)>,
+    already_processed: &HashSet<u64>,
+) -> Result<(), String> {
+    let Some(newest_id) = newest_message_id(&args.export_dir)? else {
+        println!("The export contains no messages to search from.");
+        return Ok(());
+    };
+
+    let mut guilds: BTreeMap<u64, Guild> = BTreeMap::new();
+    let mut scopes = vec![];
+    for (channel, _) in channels.iter() {
+        match &channel.guild {
+            Some(guild) => {
+                guilds.insert(guild.id, guild.clone());
+            }
+            None => scopes.push(SearchScope::Channel(channel.id)),
+        }
+    }
+    scopes.extend(guilds.keys().map(|&id| SearchScope::Guild(id)));
+
+    let mut known: HashSet<u64> = channels
+        .iter()
+        .flat_map(|(_, messages)| messages.iter().map(|m| m.id))
+        .collect();
+    let mut discovered = 0;
+
+    for scope in scopes {
+        let mut min_id = newest_id;
+        loop {
+            let page = match search_messages(&args.token, scope, user_id, min_id) {
+                Ok(page) => page,
+                Err(e) => {
+                    println!("{}", format!("Could not search {scope:?}: {e}").yellow());
+                    break;
+                }
+            };
+            // Results are sorted oldest first, so the next page starts after the newest result.
+            match page.iter().map(|m| m.id).max() {
+                Some(id) if id > min_id => min_id = id,
+                _ => break,
+            }
+
+            for found in page {
+                let guild = match scope {
+                    SearchScope::Guild(id) => guilds.get(&id),
+                    SearchScope::Channel(_) => None,
+                };
+                if add_message(args, already_processed, &mut known, channels, guild, found) {
+                    discovered += 1;
+                }
+            }
+        }
+    }
+
+    channels.sort_by_key(|(channel, _)| channel.id);
+    println!("Discovered {discovered} messages sent after the export was created.");
+    Ok(())
+}
+
+/// Adds a found message to its channel if it is selected. Returns whether it was added.
+fn add_message(
+    args: &Args,
+    already_processed: &HashSet<u64>,
+    known: &mut HashSet<u64>,
+    channels: &mut Vec<(Channel, Vec<Message>)>,
+    guild: Option<&Guild>,
+    found: FoundMessage,
+) -> bool {
+    if known.contains(&found.id) {
+        return false;
+    }
+
+    let index = match channels.iter().position(|(c, _)| c.id == found.channel_id) {
+        Some(index) => index,
+        None => {
+            // A channel that did not exist in the export (or had no messages by the user).
+            let Some(guild) = guild else {
+                return false;
+            };
+            let channel = Channel {
+                id: found.channel_id,
+                name: None,
+                channel_type: "GUILD_TEXT".to_string(),
+                guild: Some(guild.clone()),
+                recipients: None,
+            };
+            if !is_channel_selected(args, &channel) || is_blacklisted(&args.preserve_list, &channel)
+            {
+                return false;
+            }
+            channels.push((channel, vec![]));
+            channels.len() - 1
+        }
+    };
+
+    let message = Message {
+        id: found.id,
+        timestamp: found.timestamp,
+        content: found.content,
+        attachments: found
+            .attachments
+            .into_iter()
+            .map(|a| a.url)
+            .collect::<Vec<_>>()
+            .join(" "),
+    };
+    let (channel, messages) = &mut channels[index];
+    if !is_message_selected(args, already_processed, channel, &message) {
+        return false;
+    }
+    known.insert(message.id);
+    messages.push(message);
+    true
+}
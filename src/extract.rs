@@ -1,5 +1,4 @@
 use crate::Args;
-use crate::continuation::parse_continuation_file;
 use chrono::{DateTime, NaiveDateTime, Utc};
 use serde::{Deserialize, Deserializer};
 use serde_with::{DisplayFromStr, serde_as};
@@ -58,7 +57,7 @@ fn read_text_file(dir: &Path, file: &'static str) -> Result<String, String> {
     std::fs::read_to_string(&path).map_err(|e| format!("Could not read file {path:?}: {e}"))
 }
 
-fn is_blacklisted(preserve_list: &[u64], channel: &Channel) -> bool {
+pub fn is_blacklisted(preserve_list: &[u64], channel: &Channel) -> bool {
     if preserve_list.contains(&channel.id) {
         return true;
     }
@@ -86,12 +85,87 @@ fn is_whitelisted(delete_list: &[u64], channel: &Channel) -> bool {
     false
 }
 
-pub fn extract_messages(args: &Args) -> Result<Vec<(Channel, Vec<Message>)>, String> {
-    let already_processed: HashSet<u64> = match &args.continuation_file {
-        Some(path) => parse_continuation_file(path)?,
-        None => HashSet::new(),
-    };
+#[serde_as]
+#[derive(Debug, Deserialize)]
+struct Account {
+    #[serde_as(as = "DisplayFromStr")]
+    id: u64,
+}
+
+/// Reads the ID of the account the data export belongs to.
+pub fn read_account_id(export_dir: &Path) -> Result<u64, String> {
+    let mut path = export_dir.to_path_buf();
+    path.push("account");
+    let account = read_text_file(&path, "user.json")?;
+    let account: Account = serde_json::from_str(&account)
+        .map_err(|e| format!("Could not get JSON from account file in {path:?}: {e}"))?;
+    Ok(account.id)
+}
 
+/// Whether messages in this channel should be redacted according to the channel type options
+/// and the delete list. The preserve list is checked separately with [`is_blacklisted`].
+pub fn is_channel_selected(args: &Args, channel: &Channel) -> bool {
+    match channel.channel_type.as_str() {
+        "GUILD_TEXT" if !args.delete_guilds => false,
+        "DM" if !args.delete_dms => false,
+        "GROUP_DM" if !args.delete_groups => false,
+        _ => is_whitelisted(&args.delete_list, channel),
+    }
+}
+
+/// Whether a message should be redacted according to the date range, the filter
+/// and the messages processed by previous runs.
+pub fn is_message_selected(
+    args: &Args,
+    already_processed: &HashSet<u64>,
+    channel: &Channel,
+    message: &Message,
+) -> bool {
+    args.after.is_none_or(|after| message.timestamp >= after)
+        && args.before.is_none_or(|before| message.timestamp <= before)
+        && !(message.content.is_empty() && message.attachments.is_empty())
+        && !already_processed.contains(&message.id)
+        && args
+            .filter
+            .as_ref()
+            .is_none_or(|f| f.matches(channel, message))
+}
+
+#[derive(Deserialize)]
+struct MessageId {
+    #[serde(rename = "ID")]
+    id: u64,
+}
+
+/// Finds the newest message in the export, regardless of any options.
+pub fn newest_message_id(export_dir: &Path) -> Result<Option<u64>, String> {
+    let mut directory = export_dir.to_path_buf();
+    directory.push("messages");
+
+    let entries: ReadDir = directory
+        .read_dir()
+        .map_err(|e| format!("Could not get children of directory {directory:?}: {e}"))?;
+
+    let mut newest = None;
+    for entry in entries {
+        let entry: DirEntry =
+            entry.map_err(|e| format!("Could not get child of directory: {e}"))?;
+        let path: PathBuf = entry.path();
+        if !path.is_dir() {
+            continue;
+        }
+        let raw_json: String = read_text_file(&path, "messages.json")?;
+        let messages: Vec<MessageId> = serde_json::from_str(&raw_json)
+            .map_err(|e| format!("Could not get JSON from messages file in {path:?}: {e}"))?;
+        newest = newest.max(messages.iter().map(|m| m.id).max());
+    }
+    Ok(newest)
+}
+
+pub fn extract_messages(
+    args: &Args,
+    already_processed: &HashSet<u64>,
+) -> Result<Vec<(Channel, Vec<Message>)>, String> {
     if !already_processed.is_empty() {
         println!(
             "Found {} already processed messages.",
@@ -122,14 +196,7 @@ pub fn extract_messages(args: &Args) -> Result<Vec<(Channel, Vec<Message>)>, Str
             format!("Could not get JSON from channel metadata file in {path:?}: {e}")
         })?;
 
-        match channel.channel_type.as_str() {
-            "GUILD_TEXT" if !args.delete_guilds => continue,
-            "DM" if !args.delete_dms => continue,
-            "GROUP_DM" if !args.delete_groups => continue,
-            _ => {}
-        }
-
-        if !is_whitelisted(&args.delete_list, &channel) {
+        if !is_channel_selected(args, &channel) {
             continue;
         }
 
@@ -152,16 +219,13 @@ pub fn extract_messages(args: &Args) -> Result<Vec<(Channel, Vec<Message>)>, Str
 
         let messages: Vec<Message> = messages
             .into_iter()
-            .filter(|m| {
-                args.after.is_none_or(|after| m.timestamp >= after)
-                    && args.before.is_none_or(|before| m.timestamp <= before)
-                    && !(m.content.is_empty() && m.attachments.is_empty())
-                    && !already_processed.contains(&m.id)
-            })
+            .filter(|m| is_message_selected(args, already_processed, &channel, m))
             .collect();
 
         channels.push((channel, messages));
     }
 
+    // The directory order depends on the file system; keep runs reproducible.
+    channels.sort_by_key(|(channel, _)| channel.id);
     Ok(channels)
 }
@@ -0,0 +1,659 @@
+//! A small boolean expression language for selecting which messages get redacted.
+//!
+//! Example: `guild == 123 && year < 2022 && !has_attachments && content ~ "password"`
+//!
+//! Supported operators are `==`, `!=`, `<`, `<=`, `>`, `>=`, `~` (regex match), `!~`,
+//! `&&`, `||`, `!` and parentheses. Comparisons always have a field on the left
+//! and a literal (number, string, `true` or `false`) on the right.
+
+use crate::extract::{Channel, Message};
+use chrono::{Datelike, Timelike};
+use regex::Regex;
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FieldType {
+    Bool,
+    Number,
+    Text,
+}
+
+impl FieldType {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Bool => "boolean",
+            Self::Number => "number",
+            Self::Text => "string",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Id,
+    Content,
+    Attachments,
+    HasAttachments,
+    Length,
+    Year,
+    Month,
+    Day,
+    Hour,
+    Date,
+    Channel,
+    ChannelName,
+    ChannelType,
+    Guild,
+    GuildName,
+    IsGuild,
+    IsDm,
+    IsGroup,
+}
+
+const FIELDS: &[(&str, Field)] = &[
+    ("id", Field::Id),
+    ("content", Field::Content),
+    ("attachments", Field::Attachments),
+    ("has_attachments", Field::HasAttachments),
+    ("length", Field::Length),
+    ("year", Field::Year),
+    ("month", Field::Month),
+    ("day", Field::Day),
+    ("hour", Field::Hour),
+    ("date", Field::Date),
+    ("channel", Field::Channel),
+    ("channel_name", Field::ChannelName),
+    ("channel_type", Field::ChannelType),
+    ("guild", Field::Guild),
+    ("guild_name", Field::GuildName),
+    ("is_guild", Field::IsGuild),
+    ("is_dm", Field::IsDm),
+    ("is_group", Field::IsGroup),
+];
+
+impl Field {
+    fn from_name(name: &str) -> Option<Self> {
+        FIELDS.iter().find(|(n, _)| *n == name).map(|(_, f)| *f)
+    }
+
+    fn field_type(self) -> FieldType {
+        match self {
+            Self::HasAttachments | Self::IsGuild | Self::IsDm | Self::IsGroup => FieldType::Bool,
+            Self::Id
+            | Self::Length
+            | Self::Year
+            | Self::Month
+            | Self::Day
+            | Self::Hour
+            | Self::Channel
+            | Self::Guild => FieldType::Number,
+            Self::Content
+            | Self::Attachments
+            | Self::Date
+            | Self::ChannelName
+            | Self::ChannelType
+            | Self::GuildName => FieldType::Text,
+        }
+    }
+
+    /// Returns `None` if the field does not apply to this message (e.g. `guild` in a DM).
+    fn value(self, channel: &Channel, message: &Message) -> Option<Value> {
+        let ts = message.timestamp;
+        let value = match self {
+            Self::Id => Value::Number(message.id),
+            Self::Content => Value::Text(message.content.clone()),
+            Self::Attachments => Value::Text(message.attachments.clone()),
+            Self::HasAttachments => Value::Bool(!message.attachments.is_empty()),
+            Self::Length => Value::Number(message.content.chars().count() as u64),
+            Self::Year => Value::Number(ts.year() as u64),
+            Self::Month => Value::Number(ts.month() as u64),
+            Self::Day => Value::Number(ts.day() as u64),
+            Self::Hour => Value::Number(ts.hour() as u64),
+            Self::Date => Value::Text(ts.format("%Y-%m-%d").to_string()),
+            Self::Channel => Value::Number(channel.id),
+            Self::ChannelName => Value::Text(channel.name.clone()?),
+            Self::ChannelType => Value::Text(channel.channel_type.clone()),
+            Self::Guild => Value::Number(channel.guild.as_ref()?.id),
+            Self::GuildName => Value::Text(channel.guild.as_ref()?.name.clone()),
+            Self::IsGuild => Value::Bool(channel.guild.is_some()),
+            Self::IsDm => Value::Bool(channel.channel_type == "DM"),
+            Self::IsGroup => Value::Bool(channel.channel_type == "GROUP_DM"),
+        };
+        Some(value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+enum Value {
+    Bool(bool),
+    Number(u64),
+    Text(String),
+}
+
+impl Value {
+    fn field_type(&self) -> FieldType {
+        match self {
+            Self::Bool(_) => FieldType::Bool,
+            Self::Number(_) => FieldType::Number,
+            Self::Text(_) => FieldType::Text,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Literal(bool),
+    Field(Field),
+    Compare(Field, CompareOp, Value),
+    Matches(Field, Regex, bool),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    fn eval(&self, channel: &Channel, message: &Message) -> bool {
+        match self {
+            Self::Literal(b) => *b,
+            Self::Field(field) => field.value(channel, message) == Some(Value::Bool(true)),
+            Self::Compare(field, op, rhs) => {
+                let Some(lhs) = field.value(channel, message) else {
+                    // Missing values only ever satisfy `!=`.
+                    return *op == CompareOp::Ne;
+                };
+                match op {
+                    CompareOp::Eq => lhs == *rhs,
+                    CompareOp::Ne => lhs != *rhs,
+                    CompareOp::Lt => lhs < *rhs,
+                    CompareOp::Le => lhs <= *rhs,
+                    CompareOp::Gt => lhs > *rhs,
+                    CompareOp::Ge => lhs >= *rhs,
+                }
+            }
+            Self::Matches(field, regex, negated) => match field.value(channel, message) {
+                Some(Value::Text(text)) => regex.is_match(&text) != *negated,
+                _ => *negated,
+            },
+            Self::Not(inner) => !inner.eval(channel, message),
+            Self::And(a, b) => a.eval(channel, message) && b.eval(channel, message),
+            Self::Or(a, b) => a.eval(channel, message) || b.eval(channel, message),
+        }
+    }
+}
+
+/// A parsed `--filter` expression.
+#[derive(Debug, Clone)]
+pub struct Filter {
+    expr: Expr,
+}
+
+impl Filter {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let tokens = tokenize(source).map_err(|e| e.render(source))?;
+        let mut parser = Parser {
+            tokens: &tokens,
+            pos: 0,
+            source_len: source.len(),
+        };
+        let expr = parser.parse_or().map_err(|e| e.render(source))?;
+        if let Some(token) = parser.peek() {
+            return Err(
+                ParseError::new("unexpected token after end of expression", token.span)
+                    .render(source),
+            );
+        }
+        Ok(Self { expr })
+    }
+
+    pub fn matches(&self, channel: &Channel, message: &Message) -> bool {
+        self.expr.eval(channel, message)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Span {
+    start: usize,
+    end: usize,
+}
+
+struct ParseError {
+    message: String,
+    span: Span,
+}
+
+impl ParseError {
+    fn new(message: impl Into<String>, span: Span) -> Self {
+        Self {
+            message: message.into(),
+            span,
+        }
+    }
+
+    /// Renders the error with the expression and a caret line underneath the offending token.
+    fn render(&self, source: &str) -> String {
+        let padding = source[..self.span.start].chars().count();
+        let width = source[self.span.start..self.span.end]
+            .chars()
+            .count()
+            .max(1);
+        let mut out = format!("Invalid filter expression: {}\n", self.message);
+        let _ = writeln!(out, "  {source}");
+        let _ = write!(out, "  {}{}", " ".repeat(padding), "^".repeat(width));
+        out
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    Ident(String),
+    Number(u64),
+    Text(String),
+    Op(&'static str),
+    LParen,
+    RParen,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    span: Span,
+}
+
+const OPERATORS: &[&str] = &["&&", "||", "==", "!=", "<=", ">=", "!~", "<", ">", "~", "!"];
+
+fn tokenize(source: &str) -> Result<Vec<Token>, ParseError> {
+    let mut tokens = vec![];
+    let mut chars = source.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        if c == '(' || c == ')' {
+            chars.next();
+            let kind = if c == '(' {
+                TokenKind::LParen
+            } else {
+                TokenKind::RParen
+            };
+            tokens.push(Token {
+                kind,
+                span: Span {
+                    start,
+                    end: start + 1,
+                },
+            });
+            continue;
+        }
+
+        if c == '"' {
+            chars.next();
+            let mut text = String::new();
+            let mut end = None;
+            while let Some((i, c)) = chars.next() {
+                match c {
+                    '"' => {
+                        end = Some(i + 1);
+                        break;
+                    }
+                    '\\' => match chars.next() {
+                        Some((_, escaped)) => text.push(escaped),
+                        None => break,
+                    },
+                    _ => text.push(c),
+                }
+            }
+            let Some(end) = end else {
+                let span = Span {
+                    start,
+                    end: source.len(),
+                };
+                return Err(ParseError::new("unterminated string literal", span));
+            };
+            tokens.push(Token {
+                kind: TokenKind::Text(text),
+                span: Span { start, end },
+            });
+            continue;
+        }
+
+        if c.is_ascii_digit() || c.is_alphabetic() || c == '_' {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek() {
+                if !(c.is_alphanumeric() || c == '_') {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            let word = &source[start..end];
+            let span = Span { start, end };
+            let kind = if word.starts_with(|c: char| c.is_ascii_digit()) {
+                let number = word
+                    .parse()
+                    .map_err(|_| ParseError::new(format!("invalid number {word:?}"), span))?;
+                TokenKind::Number(number)
+            } else {
+                TokenKind::Ident(word.to_string())
+            };
+            tokens.push(Token { kind, span });
+            continue;
+        }
+
+        let rest = &source[start..];
+        let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(**op)) else {
+            let span = Span {
+                start,
+                end: start + c.len_utf8(),
+            };
+            return Err(ParseError::new(format!("unexpected character {c:?}"), span));
+        };
+        for _ in 0..op.len() {
+            chars.next();
+        }
+        tokens.push(Token {
+            kind: TokenKind::Op(op),
+            span: Span {
+                start,
+                end: start + op.len(),
+            },
+        });
+    }
+
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    source_len: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Result<&Token, ParseError> {
+        let end = Span {
+            start: self.source_len,
+            end: self.source_len,
+        };
+        let token = self
+            .tokens
+            .get(self.pos)
+            .ok_or_else(|| ParseError::new("unexpected end of expression", end))?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn eat_op(&mut self, op: &str) -> bool {
+        if let Some(Token {
+            kind: TokenKind::Op(found),
+            ..
+        }) = self.peek()
+            && *found == op
+        {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.parse_and()?;
+        while self.eat_op("||") {
+            let rhs = self.parse_and()?;
+            expr = Expr::Or(Box::new(expr), Box::new(rhs));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.parse_unary()?;
+        while self.eat_op("&&") {
+            let rhs = self.parse_unary()?;
+            expr = Expr::And(Box::new(expr), Box::new(rhs));
+        }
+        Ok(expr)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, ParseError> {
+        if self.eat_op("!") {
+            let inner = self.parse_unary()?;
+            return Ok(Expr::Not(Box::new(inner)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, ParseError> {
+        let token = self.next()?.clone();
+        match token.kind {
+            TokenKind::LParen => {
+                let expr = self.parse_or()?;
+                let closing = self.next()?;
+                if closing.kind != TokenKind::RParen {
+                    return Err(ParseError::new("expected `)`", closing.span));
+                }
+                Ok(expr)
+            }
+            TokenKind::Ident(name) if name == "true" => Ok(Expr::Literal(true)),
+            TokenKind::Ident(name) if name == "false" => Ok(Expr::Literal(false)),
+            TokenKind::Ident(name) => {
+                let field = Field::from_name(&name).ok_or_else(|| {
+                    let names: Vec<&str> = FIELDS.iter().map(|(n, _)| *n).collect();
+                    ParseError::new(
+                        format!(
+                            "unknown field `{name}` (expected one of {})",
+                            names.join(", ")
+                        ),
+                        token.span,
+                    )
+                })?;
+                self.parse_comparison(field, &name, token.span)
+            }
+            _ => Err(ParseError::new(
+                "expected a field name, `!` or `(`",
+                token.span,
+            )),
+        }
+    }
+
+    fn parse_comparison(
+        &mut self,
+        field: Field,
+        name: &str,
+        span: Span,
+    ) -> Result<Expr, ParseError> {
+        let field_type = field.field_type();
+        let op = match self.peek() {
+            Some(Token {
+                kind: TokenKind::Op(op),
+                ..
+            }) if !matches!(*op, "&&" | "||" | "!") => *op,
+            _ if field_type == FieldType::Bool => return Ok(Expr::Field(field)),
+            _ => {
+                return Err(ParseError::new(
+                    format!(
+                        "`{name}` is a {} field and needs a comparison",
+                        field_type.name()
+                    ),
+                    span,
+                ));
+            }
+        };
+        self.pos += 1;
+
+        let literal = self.next()?.clone();
+        let value = match literal.kind {
+            TokenKind::Number(n) => Value::Number(n),
+            TokenKind::Text(s) => Value::Text(s),
+            TokenKind::Ident(s) if s == "true" => Value::Bool(true),
+            TokenKind::Ident(s) if s == "false" => Value::Bool(false),
+            _ => return Err(ParseError::new("expected a literal value", literal.span)),
+        };
+
+        if op == "~" || op == "!~" {
+            let Value::Text(pattern) = value else {
+                return Err(ParseError::new(
+                    format!("`{op}` needs a string pattern"),
+                    literal.span,
+                ));
+            };
+            if field_type != FieldType::Text {
+                return Err(ParseError::new(
+                    format!("`{op}` can only be used on string fields"),
+                    span,
+                ));
+            }
+            let regex = Regex::new(&pattern)
+                .map_err(|e| ParseError::new(format!("invalid regex: {e}"), literal.span))?;
+            return Ok(Expr::Matches(field, regex, op == "!~"));
+        }
+
+        if value.field_type() != field_type {
+            return Err(ParseError::new(
+                format!(
+                    "`{name}` is a {} field but was compared to a {}",
+                    field_type.name(),
+                    value.field_type().name(),
+                ),
+                literal.span,
+            ));
+        }
+
+        let op = match op {
+            "==" => CompareOp::Eq,
+            "!=" => CompareOp::Ne,
+            "<" => CompareOp::Lt,
+            "<=" => CompareOp::Le,
+            ">" => CompareOp::Gt,
+            ">=" => CompareOp::Ge,
+            _ => unreachable!("all comparison operators are handled"),
+        };
+        Ok(Expr::Compare(field, op, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extract::Guild;
+    use chrono::{TimeZone, Utc};
+
+    fn message(content: &str) -> Message {
+        Message {
+            id: 1,
+            timestamp: Utc.with_ymd_and_hms(2021, 6, 1, 12, 0, 0).unwrap(),
+            content: content.to_string(),
+            attachments: String::new(),
+        }
+    }
+
+    fn dm() -> Channel {
+        Channel {
+            id: 10,
+            name: None,
+            channel_type: "DM".to_string(),
+            guild: None,
+            recipients: None,
+        }
+    }
+
+    fn guild_channel() -> Channel {
+        Channel {
+            id: 20,
+            name: Some("general".to_string()),
+            channel_type: "GUILD_TEXT".to_string(),
+            guild: Some(Guild {
+                id: 1,
+                name: "guild".to_string(),
+            }),
+            recipients: None,
+        }
+    }
+
+    fn matches(source: &str, channel: &Channel, content: &str) -> bool {
+        Filter::parse(source)
+            .unwrap()
+            .matches(channel, &message(content))
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        // Parsed as `is_dm || (is_guild && false)`.
+        assert!(matches("is_dm || is_guild && false", &dm(), ""));
+        assert!(!matches("(is_dm || is_guild) && false", &dm(), ""));
+        assert!(matches("false && is_dm || is_dm", &dm(), ""));
+    }
+
+    #[test]
+    fn not_binds_tighter_than_and() {
+        assert!(!matches("!is_dm && is_dm", &dm(), ""));
+        assert!(matches("!(is_dm && false)", &dm(), ""));
+        assert!(matches("!!is_dm", &dm(), ""));
+    }
+
+    #[test]
+    fn negated_operators_are_not_split() {
+        assert!(matches("content != \"a\"", &dm(), "b"));
+        assert!(!matches("content != \"a\"", &dm(), "a"));
+        assert!(matches("content !~ \"a\"", &dm(), "b"));
+        assert!(!matches("content !~ \"a\"", &dm(), "abc"));
+        assert!(matches("!has_attachments", &dm(), ""));
+        assert!(matches("!(content ~ \"a\")", &dm(), "b"));
+    }
+
+    #[test]
+    fn missing_values_only_satisfy_negations() {
+        assert!(matches("guild != 1", &dm(), ""));
+        assert!(!matches("guild == 1", &dm(), ""));
+        assert!(!matches("guild < 5", &dm(), ""));
+        assert!(matches("channel_name !~ \"gen\"", &dm(), ""));
+        assert!(!matches("channel_name ~ \"gen\"", &dm(), ""));
+
+        assert!(!matches("guild != 1", &guild_channel(), ""));
+        assert!(matches("channel_name ~ \"gen\"", &guild_channel(), ""));
+    }
+
+    #[test]
+    fn rejects_type_errors() {
+        let error = Filter::parse("year == \"2021\"").unwrap_err();
+        assert!(error.contains("`year` is a number field but was compared to a string"));
+
+        let error = Filter::parse("length ~ \"1\"").unwrap_err();
+        assert!(error.contains("`~` can only be used on string fields"));
+
+        let error = Filter::parse("content ~ 1").unwrap_err();
+        assert!(error.contains("`~` needs a string pattern"));
+
+        let error = Filter::parse("content").unwrap_err();
+        assert!(error.contains("`content` is a string field and needs a comparison"));
+    }
+
+    #[test]
+    fn points_at_bad_tokens_in_non_ascii_input() {
+        let error = Filter::parse("content == \"grüße\" # x").unwrap_err();
+        let (_, caret) = error.rsplit_once('\n').unwrap();
+        // `#` is the 20th character, but not the 20th byte.
+        assert_eq!(caret, format!("  {}^", " ".repeat(19)));
+        assert!(error.contains("unexpected character '#'"), "{error}");
+
+        let error = Filter::parse("content ~ \"ü\" && größe").unwrap_err();
+        let (_, caret) = error.rsplit_once('\n').unwrap();
+        assert_eq!(caret, format!("  {}^^^^^", " ".repeat(17)));
+        assert!(error.contains("unknown field `größe`"), "{error}");
+    }
+}
@@ -1,23 +1,51 @@
-use crate::cli::{Args, DeletionMode};
-use crate::continuation::write_continuation_file;
-use crate::discord::{DiscordError, delete_message, edit_message, user_get_displayname};
-use crate::extract::{Channel, Message, extract_messages};
+use crate::cli::{Args, AttachmentOnly, DeletionMode, FinalState};
+use crate::continuation::{
+    Continuation, parse_continuation_file, write_continuation_file, write_continuation_step,
+};
+use crate::corpus::Corpus;
+use crate::crypt::{decrypt, encrypt, is_encrypted, max_plaintext_length};
+use crate::discord::{
+    DiscordError, ErrorCode, RetryPolicy, delete_message, edit_message, get_current_user,
+    set_api_prefix, set_progress_bar, set_retry_policy, user_get_displayname,
+};
+use crate::discover::discover_messages;
+use crate::extract::{Channel, Message, extract_messages, read_account_id};
+use crate::markov::generate_random_words;
+use crate::mask::Masker;
+use crate::rules::{ChannelSettings, Rules};
+use crate::scheduler::Scheduler;
+use crate::scramble::scramble;
 use crate::shakespeare::generate_shakespeare;
 use clap::Parser;
 use colored::Colorize;
 use indicatif::{ProgressBar, ProgressStyle};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use reqwest::StatusCode;
 use reqwest::blocking::Client;
-use std::collections::HashMap;
+use reqwest::{Certificate, Proxy};
+use std::collections::{HashMap, HashSet};
 use std::io;
-use std::sync::LazyLock;
+use std::sync::{LazyLock, Mutex, OnceLock};
 use std::thread::sleep;
 use std::time::Duration;
 
 mod cli;
 mod continuation;
+mod corpus;
+mod crypt;
 mod discord;
+mod discover;
 mod extract;
+mod filter;
+mod markov;
+mod mask;
+mod ratelimit;
+mod rules;
+mod scheduler;
+mod scramble;
 mod shakespeare;
+mod template;
 mod user_agents;
 
 fn append_id_list(list: &mut Vec<u64>, file_content: String) -> Result<(), String> {
@@ -31,12 +59,40 @@ fn append_id_list(list: &mut Vec<u64>, file_content: String) -> Result<(), Strin
     Ok(())
 }
 
+/// The client built from the command line options, set before the first request.
+static CONFIGURED_CLIENT: OnceLock<Client> = OnceLock::new();
+
 pub static CLIENT: LazyLock<Client> = LazyLock::new(|| {
-    Client::builder()
+    CONFIGURED_CLIENT
+        .get()
+        .cloned()
+        .expect("HTTP client should be configured before the first request")
+});
+
+fn build_client(args: &Args) -> Result<Client, String> {
+    let mut builder = Client::builder()
         .user_agent(user_agents::get_random_user_agent())
+        .connect_timeout(Duration::from_secs_f64(args.connect_timeout))
+        .timeout(Duration::from_secs_f64(args.timeout));
+
+    if let Some(proxy) = &args.proxy {
+        let proxy = Proxy::all(proxy).map_err(|e| format!("Invalid proxy {proxy:?}: {e}"))?;
+        builder = builder.proxy(proxy);
+    }
+    for path in &args.ca_cert {
+        let pem = std::fs::read(path)
+            .map_err(|e| format!("Could not read CA certificate file {path:?}: {e}"))?;
+        let certificates = Certificate::from_pem_bundle(&pem)
+            .map_err(|e| format!("Invalid CA certificate file {path:?}: {e}"))?;
+        for certificate in certificates {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+
+    builder
         .build()
-        .expect("Failed to create reqwest client")
-});
+        .map_err(|e| format!("Failed to create HTTP client: {e}"))
+}
 
 fn main() {
     let args = Args::parse();
@@ -71,7 +127,87 @@ fn run(mut args: Args) -> Result<(), String> {
         return Err("Cannot use both preserve list and delete list!".to_string());
     }
 
-    let channels = extract_messages(&args)?;
+    if !args.overwrite_with.generates_content() {
+        return Err(format!(
+            "Messages cannot be overwritten with the {:?} mode",
+            args.overwrite_with,
+        ));
+    }
+
+    let overwrite_mode = match args.mode {
+        DeletionMode::OverwriteDelete => args.overwrite_with,
+        mode => mode,
+    };
+    if args.passes > 1 && !overwrite_mode.is_random() {
+        return Err(format!(
+            "Multiple passes need a mode that generates random content, not {overwrite_mode:?}"
+        ));
+    }
+    if args.final_state != FinalState::LastOverwrite && !args.mode.is_random() {
+        return Err(format!(
+            "The final state can only be changed in modes that generate random content, not {:?}",
+            args.mode,
+        ));
+    }
+
+    let placeholder_length = args.attachment_placeholder.trim().chars().count();
+    if placeholder_length == 0 || placeholder_length > args.content_limit() {
+        return Err(format!(
+            "The attachment placeholder must have between 1 and {} characters",
+            args.content_limit(),
+        ));
+    }
+
+    if args.mask_text.is_empty() {
+        return Err("The mask text must not be empty".to_string());
+    }
+
+    let corpus = match &args.corpus {
+        Some(path) => {
+            let corpus = Corpus::load(path, args.corpus_format)?;
+            println!("Loaded {} replacement texts from corpus.", corpus.len());
+            Some(corpus)
+        }
+        None => None,
+    };
+
+    CONFIGURED_CLIENT
+        .set(build_client(&args)?)
+        .expect("The HTTP client should only be configured once");
+    set_api_prefix(&args.api_base, args.api_version);
+    set_retry_policy(RetryPolicy {
+        max_retries: args.max_retries,
+        initial_delay: args.retry_delay,
+        max_delay: args.max_retry_delay,
+    });
+    let user_id = check_identity(&args)?;
+
+    let masker = Masker::new(&args.mask_pattern, &args.mask_pii, &args.mask_text);
+    let rules = Rules::load(&args)?;
+
+    let continuation = match &args.continuation_file {
+        Some(path) => parse_continuation_file(path)?,
+        None => Continuation::default(),
+    };
+
+    let mut channels = extract_messages(&args, &continuation.finished)?;
+    if args.discover_new {
+        discover_messages(&args, user_id, &mut channels, &continuation.finished)?;
+    }
+    if let Some(template) = &args.replacement_text {
+        template.validate(&channels, args.content_limit())?;
+    }
+    let settings: Vec<ChannelSettings> = channels
+        .iter()
+        .map(|(channel, _)| rules.resolve(&args, channel))
+        .collect();
+    let context = Context {
+        args: &args,
+        corpus: corpus.as_ref(),
+        masker: &masker,
+    };
+    validate_settings(&context, &channels, &settings)?;
+
     let message_count: usize = channels.iter().map(|(_, x)| x.len()).sum();
 
     let text = format!(
@@ -84,63 +220,288 @@ fn run(mut args: Args) -> Result<(), String> {
     println!("====== Press Enter to start ======");
     io::stdin().read_line(&mut String::new()).unwrap();
 
-    let mut displayname_cache = HashMap::new();
-    let mut failed_messages: Vec<Message> = vec![];
     let bar = ProgressBar::new(message_count as u64);
     bar.set_style(
         ProgressStyle::with_template("[{eta}] {wide_bar} {pos}/{len} ({percent_precise}%)")
             .unwrap(),
     );
+    set_progress_bar(bar.clone());
+    let progress = Progress {
+        bar,
+        displayname_cache: Mutex::new(HashMap::new()),
+        failed_messages: Mutex::new(vec![]),
+        unreachable: Mutex::new(vec![]),
+    };
 
-    for (channel, messages) in channels {
-        for message in messages {
-            loop {
-                let resp = handle_message(&args, &bar, &mut displayname_cache, &channel, &message);
-                if !resp.retry {
-                    if !resp.success {
-                        failed_messages.push(message);
+    let workers = (args.workers as usize).min(channels.len()).max(1);
+    let scheduler = Scheduler::new(channels.into_iter().zip(settings).map(
+        |((channel, messages), settings)| {
+            let state = ChannelState {
+                channel,
+                settings,
+                used_quotes: HashSet::new(),
+                unreachable: None,
+            };
+            (state, messages)
+        },
+    ));
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| {
+                while let Some(mut task) = scheduler.next() {
+                    let failure = redact_message(
+                        &context,
+                        &progress,
+                        &continuation,
+                        &mut task.state,
+                        &task.message,
+                    );
+                    if let Some(reason) = task.state.unreachable.take() {
+                        // Every other request in this channel would fail the same way.
+                        let (state, messages) = scheduler.abandon(task);
+                        progress.bar.inc(messages.len() as u64);
+                        progress
+                            .unreachable
+                            .lock()
+                            .expect("Unreachable messages lock should not be poisoned")
+                            .push((state.channel, reason, messages));
+                        continue;
+                    }
+                    let message = scheduler.finish(task);
+                    if let Some(failure) = failure {
+                        progress
+                            .failed_messages
+                            .lock()
+                            .expect("Failed messages lock should not be poisoned")
+                            .push((message, failure));
                     }
-                    break;
+                    progress.bar.inc(1);
                 }
-            }
-            bar.inc(1);
+            });
         }
-    }
+    });
 
-    bar.finish();
+    progress.bar.finish();
     println!("{}", "\nDone!".bright_green());
-    if !failed_messages.is_empty() {
+    let failed_messages = progress
+        .failed_messages
+        .into_inner()
+        .expect("Failed messages lock should not be poisoned");
+    let (exhausted, rejected): (Vec<_>, Vec<_>) = failed_messages
+        .into_iter()
+        .partition(|(_, failure)| *failure == Failure::RetriesExhausted);
+    if !rejected.is_empty() {
         println!("Some messages could not be redacted:");
-        for message in failed_messages {
+        for (message, _) in rejected {
             println!("{} - {:?}", message.id, message.content);
         }
     }
+    if !exhausted.is_empty() {
+        println!(
+            "Some messages failed due to server or network errors. Run again later to retry them:"
+        );
+        for (message, _) in exhausted {
+            println!("{} - {:?}", message.id, message.content);
+        }
+    }
+    let unreachable = progress
+        .unreachable
+        .into_inner()
+        .expect("Unreachable messages lock should not be poisoned");
+    if !unreachable.is_empty() {
+        println!("Some channels were unreachable:");
+        for (channel, reason, messages) in unreachable {
+            let name = channel.name.as_deref().unwrap_or("<unnamed>");
+            println!(
+                "{name:?} ({}): {} messages unreachable. {reason}",
+                channel.id,
+                messages.len(),
+            );
+            for message in messages {
+                println!("  {} - {:?}", message.id, message.content);
+            }
+        }
+    }
 
     Ok(())
 }
 
+/// Makes sure the token is valid and belongs to the account the data export is from.
+/// Returns the ID of the logged-in user.
+fn check_identity(args: &Args) -> Result<u64, String> {
+    let user = get_current_user(&args.token).map_err(|e| match e {
+        DiscordError::Api {
+            status: StatusCode::UNAUTHORIZED,
+            ..
+        } => "The token is invalid or expired".to_string(),
+        e => format!("Could not check the token: {e}"),
+    })?;
+    println!(
+        "Logged in as {} ({}).",
+        user.username.yellow(),
+        user.id.to_string().dimmed()
+    );
+
+    let export_id = match read_account_id(&args.export_dir) {
+        Ok(id) => id,
+        Err(e) if args.allow_account_mismatch => {
+            println!("{}", format!("Could not verify the account: {e}").yellow());
+            return Ok(user.id);
+        }
+        Err(e) => {
+            return Err(format!(
+                "Could not verify that the data export belongs to this account: {e}\n\
+                Pass --allow-account-mismatch to start anyway."
+            ));
+        }
+    };
+    if export_id != user.id {
+        let message = format!(
+            "The data export belongs to account {export_id}, but the token to {}",
+            user.id
+        );
+        if !args.allow_account_mismatch {
+            return Err(format!(
+                "{message}. Pass --allow-account-mismatch to start anyway."
+            ));
+        }
+        println!("{}", message.yellow());
+    }
+    Ok(user.id)
+}
+
+/// Redacts a message, retrying after rate limits. Returns why it failed, if it did.
+fn redact_message(
+    context: &Context,
+    progress: &Progress,
+    continuation: &Continuation,
+    state: &mut ChannelState,
+    message: &Message,
+) -> Option<Failure> {
+    let mut completed_steps = continuation
+        .completed_steps
+        .get(&message.id)
+        .copied()
+        .unwrap_or(0);
+    loop {
+        let resp = handle_message(
+            context,
+            &progress.bar,
+            &progress.displayname_cache,
+            state,
+            message,
+            &mut completed_steps,
+        );
+        if !resp.retry {
+            return resp.failure;
+        }
+    }
+}
+
+/// State shared between all workers during the run.
+struct Progress {
+    bar: ProgressBar,
+    displayname_cache: Mutex<HashMap<u64, String>>,
+    failed_messages: Mutex<Vec<(Message, Failure)>>,
+    /// Channels that were given up on, with the reason and their remaining messages.
+    unreachable: Mutex<Vec<(Channel, String, Vec<Message>)>>,
+}
+
+/// Everything needed to redact messages that is prepared before the run starts.
+struct Context<'a> {
+    args: &'a Args,
+    corpus: Option<&'a Corpus>,
+    masker: &'a Masker,
+}
+
+/// The channel whose messages are currently being redacted.
+struct ChannelState {
+    channel: Channel,
+    settings: ChannelSettings,
+    /// Indices of replacement texts that were already used in this channel.
+    used_quotes: HashSet<usize>,
+    /// Set when an error means that no message in this channel can be redacted.
+    unreachable: Option<String>,
+}
+
+/// A single request that is part of redacting a message.
+#[derive(Debug, Clone, Copy)]
+enum Step {
+    /// Edit the message's content to text generated by the given mode.
+    Edit(DeletionMode),
+    /// Remove the message's attachments but keep its content.
+    StripAttachments,
+    /// Only hide the message's link previews.
+    SuppressEmbeds,
+    Delete,
+}
+
+fn redaction_steps(args: &Args, mode: DeletionMode) -> Vec<Step> {
+    let (overwrite_mode, final_state) = match mode {
+        DeletionMode::Delete => return vec![Step::Delete],
+        DeletionMode::StripAttachments => return vec![Step::StripAttachments],
+        DeletionMode::SuppressEmbeds => return vec![Step::SuppressEmbeds],
+        DeletionMode::OverwriteDelete => (args.overwrite_with, FinalState::Delete),
+        // The final state follows random overwrites, e.g. not a rule's `text` or `encrypt` mode.
+        mode if !mode.is_random() => return vec![Step::Edit(mode)],
+        mode => (mode, args.final_state),
+    };
+
+    // Passes only make a difference for random content.
+    let passes = if overwrite_mode.is_random() {
+        args.passes as usize
+    } else {
+        1
+    };
+    let mut steps = vec![Step::Edit(overwrite_mode); passes];
+    match final_state {
+        FinalState::LastOverwrite => {}
+        FinalState::Text => steps.push(Step::Edit(DeletionMode::Text)),
+        FinalState::Delete => steps.push(Step::Delete),
+    }
+    steps
+}
+
 struct Response {
-    success: bool,
+    failure: Option<Failure>,
     retry: bool,
 }
 impl Response {
     fn ok() -> Self {
         Self {
-            success: true,
+            failure: None,
             retry: false,
         }
     }
 }
 
-/// Returns `[true]` if the message handling was successful; continuing to the next message.
-/// If `[false]`, a ratelimit or some other error has occurred; retry for a few more attempts.
+/// Why a message could not be redacted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Failure {
+    /// Discord rejected the request, or the replacement could not be generated.
+    Rejected,
+    /// Server or network errors persisted after retrying.
+    RetriesExhausted,
+}
+
+/// Returns no failure if the message handling was successful; continuing to the next message.
+/// If `retry` is set, a ratelimit has occurred; try again. Otherwise, the failure says what went wrong.
 fn handle_message(
-    args: &Args,
+    context: &Context,
     bar: &ProgressBar,
-    displayname_cache: &mut HashMap<u64, String>,
-    channel: &Channel,
+    displayname_cache: &Mutex<HashMap<u64, String>>,
+    state: &mut ChannelState,
     message: &Message,
+    completed_steps: &mut usize,
 ) -> Response {
+    let args = context.args;
+    let ChannelState {
+        channel,
+        settings,
+        used_quotes,
+        unreachable,
+    } = state;
+    let steps = redaction_steps(args, settings.mode);
     let channel_type = match channel.channel_type.as_str() {
         "GUILD_TEXT" => "Guild",
         "DM" => "DM",
@@ -186,32 +547,120 @@ fn handle_message(
         message.id.to_string().dimmed(),
     ));
 
-    let result = match args.mode {
-        DeletionMode::Delete => delete_message(&args.token, channel.id, message.id),
-        DeletionMode::Shakespeare => {
-            let content = generate_shakespeare(message.content.len());
-            edit_message(
+    for (index, step) in steps.iter().enumerate().skip(*completed_steps) {
+        let result = match *step {
+            Step::Delete => delete_message(&args.token, channel.id, message.id),
+            Step::Edit(mode) => {
+                let content =
+                    match generate_content(context, mode, channel, message, index, used_quotes) {
+                        Ok(content) => content,
+                        Err(error) => return handle_error(bar, DiscordError::Other(error)),
+                    };
+                let Some(content) = content else {
+                    return skip_message(args, bar, message);
+                };
+                // Restoring a message should never remove its attachments.
+                let preserve_attachments =
+                    settings.preserve_attachments || mode == DeletionMode::Restore;
+                // Discord rejects edits that leave neither content nor attachments.
+                if content.trim().is_empty()
+                    && (!preserve_attachments || message.attachments.is_empty())
+                {
+                    return handle_error(
+                        bar,
+                        DiscordError::Other(format!("The {mode:?} mode left the message empty")),
+                    );
+                }
+                edit_message(
+                    &args.token,
+                    channel.id,
+                    message.id,
+                    Some(&content),
+                    preserve_attachments,
+                    settings.suppress_embeds,
+                )
+            }
+            Step::StripAttachments if message.attachments.is_empty() => {
+                return skip_message(args, bar, message);
+            }
+            // Messages cannot be edited to have neither content nor attachments.
+            Step::StripAttachments if message.content.is_empty() => match args.attachment_only {
+                AttachmentOnly::Delete => delete_message(&args.token, channel.id, message.id),
+                AttachmentOnly::Placeholder => edit_message(
+                    &args.token,
+                    channel.id,
+                    message.id,
+                    Some(&args.attachment_placeholder),
+                    false,
+                    settings.suppress_embeds,
+                ),
+            },
+            Step::StripAttachments => edit_message(
                 &args.token,
                 channel.id,
                 message.id,
-                &content,
-                args.preserve_attachments,
-            )
+                None,
+                false,
+                settings.suppress_embeds,
+            ),
+            // Only links can have embeds.
+            Step::SuppressEmbeds if !message.content.contains("://") => {
+                return skip_message(args, bar, message);
+            }
+            Step::SuppressEmbeds => {
+                edit_message(&args.token, channel.id, message.id, None, true, true)
+            }
+        };
+        if let Err(error) = result {
+            // Someone (or an earlier run) deleted it already; there is nothing left to redact.
+            if error.code() == Some(ErrorCode::UnknownMessage) {
+                bar.println(format!(
+                    "Message {} was already deleted",
+                    message.id.to_string().dimmed()
+                ));
+                if let Some(path) = &args.continuation_file {
+                    write_continuation_file(path, message.id);
+                }
+                return Response::ok();
+            }
+            if error.code().is_some_and(ErrorCode::is_channel_wide) {
+                *unreachable = Some(error.to_string());
+            }
+            return handle_error(bar, error);
         }
-    };
 
-    let Err(error) = result else {
-        bar.println(
-            format!("Redacted message {:?}", message.content)
-                .green()
-                .to_string(),
-        );
-        if let Some(path) = &args.continuation_file {
-            write_continuation_file(path, message.id);
+        *completed_steps = index + 1;
+        if let Some(path) = &args.continuation_file
+            && *completed_steps < steps.len()
+        {
+            write_continuation_step(path, message.id, *completed_steps);
         }
-        return Response::ok();
-    };
+    }
+
+    bar.println(
+        format!("Redacted message {:?}", message.content)
+            .green()
+            .to_string(),
+    );
+    if let Some(path) = &args.continuation_file {
+        write_continuation_file(path, message.id);
+    }
+    Response::ok()
+}
 
+/// Leaves a message as it is because there is nothing to redact.
+fn skip_message(args: &Args, bar: &ProgressBar, message: &Message) -> Response {
+    bar.println(format!(
+        "Nothing to redact in message {:?}",
+        message.content
+    ));
+    if let Some(path) = &args.continuation_file {
+        write_continuation_file(path, message.id);
+    }
+    Response::ok()
+}
+
+fn handle_error(bar: &ProgressBar, error: DiscordError) -> Response {
     match error {
         DiscordError::RateLimited(retry_after) => {
             bar.println(
@@ -221,29 +670,196 @@ fn handle_message(
             );
             sleep(Duration::from_secs_f64(retry_after));
             Response {
-                success: false,
+                failure: None,
                 retry: true,
             }
         }
-        DiscordError::Other(message) => {
-            bar.println(message.red().to_string());
-            // Do not bother retrying for these errors.
+        error => {
+            bar.println(error.to_string().red().to_string());
+            let failure = match error {
+                DiscordError::RetriesExhausted { .. } => Failure::RetriesExhausted,
+                _ => Failure::Rejected,
+            };
+            // Transient errors were already retried; do not bother retrying the others.
             Response {
-                success: false,
+                failure: Some(failure),
                 retry: false,
             }
         }
     }
 }
 
-fn get_displayname(token: &str, cache: &mut HashMap<u64, String>, user_id: u64) -> String {
-    if let Some(name) = cache.get(&user_id) {
+/// The random number generator for one redaction step of a message.
+/// With `--seed`, it only depends on the seed, the message and the step.
+fn message_rng(args: &Args, message: &Message, step: usize) -> StdRng {
+    let Some(seed) = args.seed else {
+        return StdRng::from_rng(&mut rand::rng());
+    };
+    let mut bytes = [0; 32];
+    bytes[..8].copy_from_slice(&seed.to_le_bytes());
+    bytes[8..16].copy_from_slice(&message.id.to_le_bytes());
+    bytes[16..24].copy_from_slice(&(step as u64).to_le_bytes());
+    StdRng::from_seed(bytes)
+}
+
+/// Generates the replacement content for edit-based modes.
+/// Returns `None` if the message should be left as is.
+fn generate_content(
+    context: &Context,
+    mode: DeletionMode,
+    channel: &Channel,
+    message: &Message,
+    step: usize,
+    used_quotes: &mut HashSet<usize>,
+) -> Result<Option<String>, String> {
+    let args = context.args;
+    let length = message.content.chars().count();
+    let rng = &mut message_rng(args, message, step);
+    // Avoiding repeated quotes would make the output depend on the order of messages.
+    let mut unused = HashSet::new();
+    let used_quotes = if args.seed.is_some() {
+        &mut unused
+    } else {
+        used_quotes
+    };
+    let content = match mode {
+        DeletionMode::Shakespeare => generate_shakespeare(
+            rng,
+            length,
+            args.content_limit(),
+            args.match_length,
+            used_quotes,
+        ),
+        DeletionMode::RandomWords => generate_random_words(
+            rng,
+            length,
+            message.content.lines().count(),
+            args.content_limit(),
+        ),
+        DeletionMode::Corpus => context
+            .corpus
+            .expect("corpus should be loaded in corpus mode")
+            .generate(
+                rng,
+                length,
+                args.content_limit(),
+                args.match_length,
+                used_quotes,
+            ),
+        DeletionMode::Text => args
+            .replacement_text
+            .as_ref()
+            .expect("replacement text should be set in text mode")
+            .render(channel, message),
+        DeletionMode::Mask => {
+            let Some(masked) = context.masker.apply(&message.content) else {
+                return Ok(None);
+            };
+            // Longer masks can push a message over the limit.
+            let length = masked.chars().count();
+            if length > args.content_limit() {
+                return Err(format!(
+                    "The masked message would have {length} characters, but Discord only allows {}",
+                    args.content_limit(),
+                ));
+            }
+            masked
+        }
+        DeletionMode::Scramble => scramble(rng, &message.content),
+        DeletionMode::Encrypt => {
+            encrypt(passphrase(context), &message.content, args.content_limit())?
+        }
+        DeletionMode::Restore => {
+            if !is_encrypted(&message.content) {
+                return Ok(None);
+            }
+            decrypt(passphrase(context), &message.content)?
+        }
+        DeletionMode::Delete
+        | DeletionMode::OverwriteDelete
+        | DeletionMode::StripAttachments
+        | DeletionMode::SuppressEmbeds => {
+            unreachable!("{mode:?} mode does not generate content")
+        }
+    };
+    Ok(Some(content))
+}
+
+fn passphrase<'a>(context: &Context<'a>) -> &'a str {
+    context
+        .args
+        .passphrase
+        .as_deref()
+        .expect("passphrase should be set in encrypt and restore mode")
+}
+
+/// Makes sure everything the modes used in each channel need was provided,
+/// that every message can be encrypted, and that the passphrase is correct when restoring.
+fn validate_settings(
+    context: &Context,
+    channels: &[(Channel, Vec<Message>)],
+    settings: &[ChannelSettings],
+) -> Result<(), String> {
+    let args = context.args;
+    let mut modes: HashMap<DeletionMode, Vec<&Message>> = HashMap::new();
+    for ((_, messages), settings) in channels.iter().zip(settings) {
+        for step in redaction_steps(args, settings.mode) {
+            if let Step::Edit(mode) = step {
+                modes.entry(mode).or_default().extend(messages);
+            }
+        }
+    }
+
+    for (mode, messages) in modes {
+        let missing = match mode {
+            DeletionMode::Corpus if context.corpus.is_none() => "--corpus",
+            DeletionMode::Text if args.replacement_text.is_none() => "--replacement-text",
+            DeletionMode::Mask if context.masker.is_empty() => "--mask-pattern or --mask-pii",
+            DeletionMode::Encrypt | DeletionMode::Restore if args.passphrase.is_none() => {
+                "--passphrase"
+            }
+            DeletionMode::Encrypt => {
+                let max_length = max_plaintext_length(args.content_limit());
+                let too_long = messages
+                    .iter()
+                    .filter(|m| m.content.len() > max_length)
+                    .count();
+                if too_long > 0 {
+                    return Err(format!(
+                        "{too_long} messages are longer than {max_length} bytes and cannot be encrypted. \
+                        Exclude them, e.g. with `--filter \"length <= 1000\"`, and redact them with another mode."
+                    ));
+                }
+                continue;
+            }
+            DeletionMode::Restore => {
+                let Some(message) = messages.iter().find(|m| is_encrypted(&m.content)) else {
+                    return Err("The export does not contain any encrypted messages".to_string());
+                };
+                decrypt(passphrase(context), &message.content)
+                    .map_err(|e| format!("Message {}: {e}", message.id))?;
+                continue;
+            }
+            _ => continue,
+        };
+        return Err(format!("The {mode:?} mode needs {missing}"));
+    }
+    Ok(())
+}
+
+fn get_displayname(token: &str, cache: &Mutex<HashMap<u64, String>>, user_id: u64) -> String {
+    let lock = || {
+        cache
+            .lock()
+            .expect("Display name cache lock should not be poisoned")
+    };
+    if let Some(name) = lock().get(&user_id) {
         return name.clone();
     }
 
     match user_get_displayname(token, user_id) {
         Ok(display_name) => {
-            cache.insert(user_id, display_name.clone());
+            lock().insert(user_id, display_name.clone());
             display_name
         }
         Err(_) => "<unknown user>".to_string(),
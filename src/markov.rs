@@ -0,0 +1,141 @@
+use crate::shakespeare::RAW_QUOTES_CONTENT;
+use rand::Rng;
+use rand::prelude::IndexedRandom;
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+type State = (&'static str, &'static str);
+
+/// An order-2 word Markov chain trained on the bundled (public domain) Shakespeare corpus.
+struct Chain {
+    transitions: HashMap<State, Vec<&'static str>>,
+    sentence_starts: Vec<State>,
+}
+
+static CHAIN: LazyLock<Chain> = LazyLock::new(|| {
+    let text = str::from_utf8(RAW_QUOTES_CONTENT).expect("Shakespeare corpus should be UTF-8");
+
+    // Speaker names and headings are written in all caps; only keep the actual quotes.
+    let words: Vec<&str> = text
+        .lines()
+        .filter(|line| line.chars().any(|c| c.is_lowercase()))
+        .flat_map(str::split_whitespace)
+        .collect();
+
+    let mut transitions: HashMap<State, Vec<&str>> = HashMap::new();
+    let mut sentence_starts = vec![];
+
+    for window in words.windows(3) {
+        transitions
+            .entry((window[0], window[1]))
+            .or_default()
+            .push(window[2]);
+    }
+    for (i, window) in words.windows(3).enumerate() {
+        let follows_sentence_end = i == 0 || ends_sentence(words[i - 1]);
+        if follows_sentence_end && window[0].starts_with(char::is_uppercase) {
+            sentence_starts.push((window[0], window[1]));
+        }
+    }
+
+    assert!(
+        !sentence_starts.is_empty(),
+        "Shakespeare corpus should contain sentences"
+    );
+    Chain {
+        transitions,
+        sentence_starts,
+    }
+});
+
+fn ends_sentence(word: &str) -> bool {
+    word.ends_with(['.', '!', '?'])
+}
+
+fn generate_line(chain: &Chain, rng: &mut impl Rng, target_length: usize) -> String {
+    let mut line = String::new();
+    let mut length = 0;
+
+    while length < target_length {
+        let &(first, second) = chain
+            .sentence_starts
+            .choose(rng)
+            .expect("sentence starts should not be empty");
+        let mut state = (first, second);
+
+        for word in [first, second] {
+            if length > 0 {
+                line.push(' ');
+                length += 1;
+            }
+            line.push_str(word);
+            length += word.chars().count();
+        }
+
+        // Keep walking the chain until the sentence ends and the line is long enough,
+        // or the chain runs into a dead end.
+        while length < target_length || !ends_sentence(state.1) {
+            let Some(next) = chain
+                .transitions
+                .get(&state)
+                .and_then(|words| words.choose(rng))
+            else {
+                break;
+            };
+            line.push(' ');
+            line.push_str(next);
+            length += next.chars().count() + 1;
+            state = (state.1, next);
+
+            if length >= target_length * 2 {
+                break;
+            }
+        }
+    }
+
+    let trimmed = line.trim_end_matches([',', ';', ':', '-']);
+    let mut line = trimmed.to_string();
+    if !ends_sentence(&line) {
+        line.push('.');
+    }
+    line
+}
+
+/// Generates nonsense sentences with approximately the given amount of characters,
+/// spread across the given amount of lines.
+pub fn generate_random_words(
+    rng: &mut impl Rng,
+    length: usize,
+    line_count: usize,
+    content_limit: usize,
+) -> String {
+    let chain = &*CHAIN;
+    let line_count = line_count.clamp(1, 50);
+    let line_length = (length / line_count).max(1);
+
+    let mut lines: Vec<String> = (0..line_count)
+        .map(|_| {
+            let target_length = rng.random_range(line_length / 2..=line_length).max(1);
+            generate_line(chain, rng, target_length)
+        })
+        .collect();
+
+    // Drop trailing lines until the result fits into a message.
+    while lines.len() > 1
+        && lines.iter().map(|l| l.chars().count() + 1).sum::<usize>() > content_limit
+    {
+        lines.pop();
+    }
+
+    let result = lines.join("\n");
+    if result.chars().count() <= content_limit {
+        return result;
+    }
+    // A single enormous line; cut it at a word boundary.
+    let mut truncated: String = result.chars().take(content_limit - 1).collect();
+    if let Some(space) = truncated.rfind(' ') {
+        truncated.truncate(space);
+    }
+    truncated.push('.');
+    truncated
+}
@@ -0,0 +1,117 @@
+use crate::cli::PiiCategory;
+use crate::scramble::MENTION;
+use regex::Regex;
+
+impl PiiCategory {
+    fn pattern(self) -> &'static str {
+        match self {
+            Self::Email => r"[A-Za-z0-9._%+-]+@[A-Za-z0-9-]+(?:\.[A-Za-z0-9-]+)*\.[A-Za-z]{2,}",
+            Self::Phone => r"(?:\+\d{1,3}[\s.-]?)?\(?\d{2,4}\)?[\s.-]?\d{3,4}[\s.-]?\d{3,5}\b",
+            Self::Ip => r"\b(?:(?:25[0-5]|2[0-4]\d|1?\d?\d)\.){3}(?:25[0-5]|2[0-4]\d|1?\d?\d)\b",
+            Self::CreditCard => r"\b(?:\d[ -]?){12,18}\d\b",
+        }
+    }
+}
+
+/// Replaces sensitive parts of a message while leaving the rest intact.
+#[derive(Debug)]
+pub struct Masker {
+    regexes: Vec<Regex>,
+    mask: String,
+}
+
+impl Masker {
+    pub fn new(patterns: &[Regex], categories: &[PiiCategory], mask: &str) -> Self {
+        let mut regexes = patterns.to_vec();
+        for category in categories {
+            regexes.push(Regex::new(category.pattern()).expect("PII patterns should be valid"));
+        }
+        Self {
+            regexes,
+            mask: mask.to_string(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.regexes.is_empty()
+    }
+
+    /// Returns `None` if nothing in the content matched.
+    /// Mentions are never masked, since the IDs in them look like phone or card numbers.
+    pub fn apply(&self, content: &str) -> Option<String> {
+        let mentions: Vec<(usize, usize)> = MENTION
+            .find_iter(content)
+            .map(|m| (m.start(), m.end()))
+            .collect();
+        let mut spans: Vec<(usize, usize)> = self
+            .regexes
+            .iter()
+            .flat_map(|regex| regex.find_iter(content))
+            .filter(|m| !m.is_empty())
+            .map(|m| (m.start(), m.end()))
+            .filter(|&(start, end)| {
+                !mentions
+                    .iter()
+                    .any(|&(m_start, m_end)| start < m_end && m_start < end)
+            })
+            .collect();
+        if spans.is_empty() {
+            return None;
+        }
+
+        // Merge overlapping matches of different patterns so each region is masked once.
+        spans.sort_unstable();
+        let mut merged: Vec<(usize, usize)> = vec![];
+        for (start, end) in spans {
+            match merged.last_mut() {
+                Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+
+        let mut result = String::with_capacity(content.len());
+        let mut position = 0;
+        for (start, end) in merged {
+            result.push_str(&content[position..start]);
+            result.push_str(&self.mask);
+            position = end;
+        }
+        result.push_str(&content[position..]);
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn masker(categories: &[PiiCategory]) -> Masker {
+        Masker::new(&[], categories, "█")
+    }
+
+    #[test]
+    fn masks_personal_data() {
+        let masker = masker(&[PiiCategory::Email, PiiCategory::Phone]);
+        assert_eq!(
+            masker
+                .apply("mail a@example.com or call +1 555 123 4567")
+                .unwrap(),
+            "mail █ or call █"
+        );
+        assert_eq!(masker.apply("nothing here"), None);
+    }
+
+    #[test]
+    fn keeps_mentions_and_timestamps() {
+        let masker = masker(&[PiiCategory::Phone, PiiCategory::CreditCard]);
+        let content = "<@123456789012345678> <#123456789012345678> <@&1234567890123456789> \
+                       <t:1700000000:R> <:wave:123456789012345678>";
+        assert_eq!(masker.apply(content), None);
+        assert_eq!(
+            masker
+                .apply("<@123456789012345678> card 4111 1111 1111 1111")
+                .unwrap(),
+            "<@123456789012345678> card █"
+        );
+    }
+}
@@ -0,0 +1,210 @@
+use reqwest::header::HeaderMap;
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+/// An API route, e.g. `PATCH /channels/{channel_id}/messages/{message_id}`.
+/// Discord shares rate limits between routes with the same bucket and major parameter (the channel).
+pub struct Route {
+    pub name: &'static str,
+    pub major_parameter: Option<u64>,
+}
+
+impl Route {
+    fn key(&self) -> String {
+        format!("{}:{}", self.name, self.major_parameter.unwrap_or_default())
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    remaining: u32,
+    reset_at: Instant,
+}
+
+#[derive(Debug, Default)]
+struct RateLimiter {
+    /// The bucket hash Discord reported for each route.
+    bucket_hashes: HashMap<&'static str, String>,
+    /// Keyed by bucket hash (or route name, if unknown yet) and major parameter.
+    buckets: HashMap<String, Bucket>,
+    /// Set after hitting the global rate limit, which applies to all routes.
+    global_reset_at: Option<Instant>,
+}
+
+impl RateLimiter {
+    fn bucket_key(&self, route: &Route) -> String {
+        match self.bucket_hashes.get(route.name) {
+            Some(hash) => format!("{hash}:{}", route.major_parameter.unwrap_or_default()),
+            None => route.key(),
+        }
+    }
+
+    /// Takes one request from the route's bucket, or returns how long to wait before trying again.
+    fn try_acquire(&mut self, route: &Route, now: Instant) -> Option<Duration> {
+        if let Some(reset_at) = self.global_reset_at {
+            if reset_at > now {
+                return Some(reset_at - now);
+            }
+            self.global_reset_at = None;
+        }
+
+        let key = self.bucket_key(route);
+        let bucket = self.buckets.get_mut(&key)?;
+        if bucket.reset_at <= now {
+            // The bucket was refilled; the next response tells us its new state.
+            self.buckets.remove(&key);
+            return None;
+        }
+        if bucket.remaining == 0 {
+            return Some(bucket.reset_at - now);
+        }
+        bucket.remaining -= 1;
+        None
+    }
+}
+
+static LIMITER: LazyLock<Mutex<RateLimiter>> = LazyLock::new(Default::default);
+
+/// Waits until a request on this route will not exceed a known rate limit.
+pub fn wait(route: &Route) {
+    loop {
+        let delay = LIMITER
+            .lock()
+            .expect("Rate limiter lock should not be poisoned")
+            .try_acquire(route, Instant::now());
+        match delay {
+            Some(delay) => sleep(delay),
+            None => return,
+        }
+    }
+}
+
+fn header<T: std::str::FromStr>(headers: &HeaderMap, name: &str) -> Option<T> {
+    headers.get(name)?.to_str().ok()?.parse().ok()
+}
+
+/// A duration header in seconds. Values like `inf` parse as floats but are no valid durations.
+fn seconds(headers: &HeaderMap, name: &str) -> Option<f64> {
+    header::<f64>(headers, name)
+        .filter(|seconds| seconds.is_finite())
+        .map(|seconds| seconds.max(0.0))
+}
+
+/// Updates the route's bucket from the `X-RateLimit-*` headers of a response.
+/// For 429 responses, returns how many seconds to wait before retrying.
+pub fn update(route: &Route, headers: &HeaderMap, is_rate_limited: bool) -> Option<f64> {
+    let now = Instant::now();
+    let mut limiter = LIMITER
+        .lock()
+        .expect("Rate limiter lock should not be poisoned");
+
+    if let Some(hash) = header::<String>(headers, "x-ratelimit-bucket") {
+        limiter.bucket_hashes.insert(route.name, hash);
+    }
+    let remaining = header::<u32>(headers, "x-ratelimit-remaining");
+    let reset_after = seconds(headers, "x-ratelimit-reset-after");
+    if let (Some(remaining), Some(reset_after)) = (remaining, reset_after) {
+        let key = limiter.bucket_key(route);
+        limiter.buckets.insert(
+            key,
+            Bucket {
+                remaining,
+                reset_at: now + Duration::from_secs_f64(reset_after),
+            },
+        );
+    }
+
+    if !is_rate_limited {
+        return None;
+    }
+    let retry_after = seconds(headers, "retry-after").or(reset_after)?;
+    let is_global = header::<bool>(headers, "x-ratelimit-global").unwrap_or(false)
+        || header::<String>(headers, "x-ratelimit-scope").as_deref() == Some("global");
+    if is_global {
+        limiter.global_reset_at = Some(now + Duration::from_secs_f64(retry_after));
+    }
+    Some(retry_after)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    const ROUTE: Route = Route {
+        name: "DELETE /channels/{channel_id}/messages/{message_id}",
+        major_parameter: Some(1),
+    };
+
+    fn limiter_with_bucket(remaining: u32, reset_at: Instant) -> RateLimiter {
+        let mut limiter = RateLimiter::default();
+        limiter.buckets.insert(
+            ROUTE.key(),
+            Bucket {
+                remaining,
+                reset_at,
+            },
+        );
+        limiter
+    }
+
+    #[test]
+    fn waits_for_exhausted_buckets() {
+        let now = Instant::now();
+        let reset_at = now + Duration::from_secs(2);
+        let mut limiter = limiter_with_bucket(1, reset_at);
+
+        assert_eq!(limiter.try_acquire(&ROUTE, now), None);
+        assert_eq!(
+            limiter.try_acquire(&ROUTE, now),
+            Some(Duration::from_secs(2))
+        );
+        // Other channels have their own buckets.
+        let other = Route {
+            major_parameter: Some(2),
+            ..ROUTE
+        };
+        assert_eq!(limiter.try_acquire(&other, now), None);
+    }
+
+    #[test]
+    fn refills_buckets_after_their_reset() {
+        let now = Instant::now();
+        let reset_at = now + Duration::from_secs(2);
+        let mut limiter = limiter_with_bucket(0, reset_at);
+
+        assert_eq!(limiter.try_acquire(&ROUTE, reset_at), None);
+        assert!(limiter.buckets.is_empty());
+    }
+
+    #[test]
+    fn global_limit_applies_to_all_routes() {
+        let now = Instant::now();
+        let mut limiter = RateLimiter {
+            global_reset_at: Some(now + Duration::from_secs(1)),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            limiter.try_acquire(&ROUTE, now),
+            Some(Duration::from_secs(1))
+        );
+        assert_eq!(
+            limiter.try_acquire(&ROUTE, now + Duration::from_secs(1)),
+            None
+        );
+        assert_eq!(limiter.global_reset_at, None);
+    }
+
+    #[test]
+    fn ignores_infinite_durations() {
+        let mut headers = HeaderMap::new();
+        headers.insert("retry-after", HeaderValue::from_static("inf"));
+        headers.insert("x-ratelimit-reset-after", HeaderValue::from_static("-1.5"));
+
+        assert_eq!(seconds(&headers, "retry-after"), None);
+        assert_eq!(seconds(&headers, "x-ratelimit-reset-after"), Some(0.0));
+    }
+}
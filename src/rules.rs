@@ -0,0 +1,239 @@
+use crate::cli::{Args, DeletionMode};
+use crate::extract::Channel;
+use serde::Deserialize;
+use serde_with::{DisplayFromStr, PickFirst, serde_as};
+use std::path::Path;
+
+/// Which channels a rule applies to.
+#[derive(Debug, Clone)]
+enum Selector {
+    /// A channel type like `DM` or `GROUP_DM`, or `GUILD` for all guild channels.
+    ChannelType(String),
+    Guild(u64),
+    Channel(u64),
+}
+
+impl Selector {
+    /// More specific rules override less specific ones.
+    fn specificity(&self) -> u8 {
+        match self {
+            Self::ChannelType(t) if t == "GUILD" => 0,
+            Self::ChannelType(_) => 1,
+            Self::Guild(_) => 2,
+            Self::Channel(_) => 3,
+        }
+    }
+
+    fn matches(&self, channel: &Channel) -> bool {
+        match self {
+            Self::ChannelType(t) if t == "GUILD" => channel.guild.is_some(),
+            Self::ChannelType(t) => channel.channel_type == *t,
+            Self::Guild(id) => channel.guild.as_ref().is_some_and(|g| g.id == *id),
+            Self::Channel(id) => channel.id == *id,
+        }
+    }
+}
+
+fn normalize_channel_type(name: &str) -> String {
+    match name.to_ascii_lowercase().as_str() {
+        "dm" | "dms" => "DM".to_string(),
+        "group" | "groups" | "group_dm" | "group-dm" => "GROUP_DM".to_string(),
+        "guild" | "guilds" => "GUILD".to_string(),
+        _ => name.to_ascii_uppercase().replace('-', "_"),
+    }
+}
+
+/// Overrides the redaction mode and options for some channels.
+#[derive(Debug, Clone)]
+pub struct Rule {
+    selector: Selector,
+    mode: Option<DeletionMode>,
+    preserve_attachments: Option<bool>,
+    suppress_embeds: Option<bool>,
+}
+
+/// Parses a rule given on the command line, e.g. `dm=delete`, `guild:123=text`
+/// or `channel:456=shakespeare,preserve-attachments`.
+///
+/// Option names never collide with mode names, so e.g. `dm=strip-attachments` selects the mode.
+pub fn parse_rule(s: &str) -> Result<Rule, String> {
+    let (selector, options) = s
+        .split_once('=')
+        .ok_or_else(|| format!("Invalid rule {s:?}: expected `<selector>=<mode>`"))?;
+
+    let selector = match selector.split_once(':') {
+        Some(("guild", id)) => Selector::Guild(parse_id(id)?),
+        Some(("channel", id)) => Selector::Channel(parse_id(id)?),
+        Some((kind, _)) => {
+            return Err(format!(
+                "Invalid rule selector {kind:?}: expected `guild:<id>` or `channel:<id>`"
+            ));
+        }
+        None => Selector::ChannelType(normalize_channel_type(selector)),
+    };
+
+    let mut rule = Rule {
+        selector,
+        mode: None,
+        preserve_attachments: None,
+        suppress_embeds: None,
+    };
+    for option in options.split(',').map(str::trim).filter(|o| !o.is_empty()) {
+        match option {
+            "preserve-attachments" => rule.preserve_attachments = Some(true),
+            "remove-attachments" => rule.preserve_attachments = Some(false),
+            "hide-embeds" => rule.suppress_embeds = Some(true),
+            "keep-embeds" => rule.suppress_embeds = Some(false),
+            mode => {
+                let mode = <DeletionMode as clap::ValueEnum>::from_str(mode, true)
+                    .map_err(|_| format!("Invalid mode {mode:?} in rule {s:?}"))?;
+                if let Some(previous) = rule.mode.replace(mode) {
+                    return Err(format!(
+                        "Rule {s:?} has more than one mode ({previous:?} and {mode:?})"
+                    ));
+                }
+            }
+        }
+    }
+    Ok(rule)
+}
+
+fn parse_id(s: &str) -> Result<u64, String> {
+    s.trim().parse().map_err(|_| format!("Invalid ID {s:?}"))
+}
+
+#[serde_as]
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawRule {
+    channel_type: Option<String>,
+    #[serde_as(as = "Option<PickFirst<(_, DisplayFromStr)>>")]
+    #[serde(default)]
+    guild: Option<u64>,
+    #[serde_as(as = "Option<PickFirst<(_, DisplayFromStr)>>")]
+    #[serde(default)]
+    channel: Option<u64>,
+    mode: Option<DeletionMode>,
+    preserve_attachments: Option<bool>,
+    suppress_embeds: Option<bool>,
+}
+
+impl TryFrom<RawRule> for Rule {
+    type Error = String;
+
+    fn try_from(raw: RawRule) -> Result<Self, String> {
+        let selector = match (raw.channel_type, raw.guild, raw.channel) {
+            (Some(t), None, None) => Selector::ChannelType(normalize_channel_type(&t)),
+            (None, Some(id), None) => Selector::Guild(id),
+            (None, None, Some(id)) => Selector::Channel(id),
+            _ => {
+                return Err(
+                    "Every rule needs exactly one of `channel_type`, `guild` or `channel`"
+                        .to_string(),
+                );
+            }
+        };
+        Ok(Self {
+            selector,
+            mode: raw.mode,
+            preserve_attachments: raw.preserve_attachments,
+            suppress_embeds: raw.suppress_embeds,
+        })
+    }
+}
+
+fn read_rules_file(path: &Path) -> Result<Vec<Rule>, String> {
+    let content =
+        std::fs::read_to_string(path).map_err(|e| format!("Could not read rules file: {e}"))?;
+    let raw: Vec<RawRule> = serde_json::from_str(&content)
+        .map_err(|e| format!("Could not parse rules file {path:?}: {e}"))?;
+    raw.into_iter().map(Rule::try_from).collect()
+}
+
+/// The settings used to redact all messages in one channel.
+#[derive(Debug, Clone)]
+pub struct ChannelSettings {
+    pub mode: DeletionMode,
+    pub preserve_attachments: bool,
+    pub suppress_embeds: bool,
+}
+
+#[derive(Debug)]
+pub struct Rules {
+    /// Sorted from least to most specific, so later rules take precedence.
+    rules: Vec<Rule>,
+}
+
+impl Rules {
+    /// Collects the rules from the rules file first, then the ones given on the command line.
+    pub fn load(args: &Args) -> Result<Self, String> {
+        let mut rules = match &args.rules_file {
+            Some(path) => read_rules_file(path)?,
+            None => vec![],
+        };
+        rules.extend(args.rule.iter().cloned());
+        // Stable sort: rules of the same specificity keep their order.
+        rules.sort_by_key(|rule| rule.selector.specificity());
+        Ok(Self { rules })
+    }
+
+    pub fn resolve(&self, args: &Args, channel: &Channel) -> ChannelSettings {
+        let mut settings = ChannelSettings {
+            mode: args.mode,
+            preserve_attachments: args.preserve_attachments,
+            suppress_embeds: args.suppress_embeds,
+        };
+        for rule in self.rules.iter().filter(|r| r.selector.matches(channel)) {
+            if let Some(mode) = rule.mode {
+                settings.mode = mode;
+            }
+            if let Some(preserve) = rule.preserve_attachments {
+                settings.preserve_attachments = preserve;
+            }
+            if let Some(suppress) = rule.suppress_embeds {
+                settings.suppress_embeds = suppress;
+            }
+        }
+        settings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mode_names_select_modes() {
+        let rule = parse_rule("dm=strip-attachments").unwrap();
+        assert_eq!(rule.mode, Some(DeletionMode::StripAttachments));
+        assert_eq!(rule.preserve_attachments, None);
+    }
+
+    #[test]
+    fn options_do_not_change_the_mode() {
+        let rule = parse_rule("guild:1=remove-attachments").unwrap();
+        assert_eq!(rule.mode, None);
+        assert_eq!(rule.preserve_attachments, Some(false));
+
+        let rule = parse_rule("channel:2=text,preserve-attachments").unwrap();
+        assert_eq!(rule.mode, Some(DeletionMode::Text));
+        assert_eq!(rule.preserve_attachments, Some(true));
+    }
+
+    #[test]
+    fn embed_options_do_not_shadow_the_mode() {
+        let rule = parse_rule("guild:1=suppress-embeds").unwrap();
+        assert_eq!(rule.mode, Some(DeletionMode::SuppressEmbeds));
+        assert_eq!(rule.suppress_embeds, None);
+
+        let rule = parse_rule("guild:1=hide-embeds").unwrap();
+        assert_eq!(rule.mode, None);
+        assert_eq!(rule.suppress_embeds, Some(true));
+    }
+
+    #[test]
+    fn rejects_several_modes() {
+        let error = parse_rule("dm=shakespeare,strip-attachments").unwrap_err();
+        assert!(error.contains("more than one mode"), "{error}");
+    }
+}
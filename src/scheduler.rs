@@ -0,0 +1,108 @@
+use crate::extract::Message;
+use std::collections::VecDeque;
+use std::sync::{Condvar, Mutex};
+
+/// A channel's remaining messages and its state, which is taken out while a worker handles a message.
+struct Slot<S> {
+    state: Option<S>,
+    messages: VecDeque<Message>,
+}
+
+struct Queue<S> {
+    slots: Vec<Slot<S>>,
+    /// The slot that gets the next turn.
+    position: usize,
+}
+
+/// Hands out messages one channel after another (round-robin), so consecutive requests
+/// go to different channels and no time is wasted waiting for a single channel's rate limit.
+///
+/// A channel is only given to one worker at a time, so messages within a channel stay in order.
+/// With a single worker, the order only depends on the input and is the same on every run.
+pub struct Scheduler<S> {
+    queue: Mutex<Queue<S>>,
+    /// Notified whenever a channel is returned.
+    returned: Condvar,
+}
+
+/// A message to redact, together with the state of its channel.
+pub struct Task<S> {
+    slot: usize,
+    pub state: S,
+    pub message: Message,
+}
+
+impl<S> Scheduler<S> {
+    pub fn new(channels: impl IntoIterator<Item = (S, Vec<Message>)>) -> Self {
+        let slots = channels
+            .into_iter()
+            .map(|(state, messages)| Slot {
+                state: Some(state),
+                messages: messages.into(),
+            })
+            .collect();
+        Self {
+            queue: Mutex::new(Queue { slots, position: 0 }),
+            returned: Condvar::new(),
+        }
+    }
+
+    /// Returns the next message, waiting if all channels with messages left are busy.
+    /// Returns `None` once no messages are left.
+    pub fn next(&self) -> Option<Task<S>> {
+        let mut queue = self.lock();
+        loop {
+            if queue.slots.iter().all(|slot| slot.messages.is_empty()) {
+                return None;
+            }
+
+            let count = queue.slots.len();
+            let available = (0..count)
+                .map(|offset| (queue.position + offset) % count)
+                .find(|&i| queue.slots[i].state.is_some() && !queue.slots[i].messages.is_empty());
+
+            if let Some(slot) = available {
+                queue.position = (slot + 1) % count;
+                let Slot { state, messages } = &mut queue.slots[slot];
+                let state = state.take().expect("Available slot should have a state");
+                let message = messages
+                    .pop_front()
+                    .expect("Available slot should have messages");
+                return Some(Task {
+                    slot,
+                    state,
+                    message,
+                });
+            }
+
+            queue = self
+                .returned
+                .wait(queue)
+                .expect("Scheduler lock should not be poisoned");
+        }
+    }
+
+    /// Hands a channel back after its message was handled, so the next one can be scheduled.
+    pub fn finish(&self, task: Task<S>) -> Message {
+        self.lock().slots[task.slot].state = Some(task.state);
+        self.returned.notify_all();
+        task.message
+    }
+
+    /// Removes the rest of a channel's messages, e.g. when the channel cannot be accessed anymore.
+    /// Returns the channel's state and all its unhandled messages, including the task's.
+    pub fn abandon(&self, task: Task<S>) -> (S, Vec<Message>) {
+        let remaining = std::mem::take(&mut self.lock().slots[task.slot].messages);
+        // Workers waiting for this channel may now find that no messages are left.
+        self.returned.notify_all();
+        let mut messages = vec![task.message];
+        messages.extend(remaining);
+        (task.state, messages)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Queue<S>> {
+        self.queue
+            .lock()
+            .expect("Scheduler lock should not be poisoned")
+    }
+}
@@ -0,0 +1,162 @@
+use crate::shakespeare::RAW_QUOTES_CONTENT;
+use rand::Rng;
+use rand::prelude::IndexedRandom;
+use regex::Regex;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::LazyLock;
+
+/// Words from the bundled Shakespeare corpus, grouped by length.
+static DICTIONARY: LazyLock<BTreeMap<usize, Vec<&'static str>>> = LazyLock::new(|| {
+    let text = str::from_utf8(RAW_QUOTES_CONTENT).expect("Shakespeare corpus should be UTF-8");
+    let words: BTreeSet<&str> = text
+        .split_whitespace()
+        .map(|word| word.trim_matches(|c: char| c.is_ascii_punctuation()))
+        .filter(|word| !word.is_empty() && word.bytes().all(|b| b.is_ascii_lowercase()))
+        .collect();
+
+    let mut dictionary: BTreeMap<usize, Vec<&str>> = BTreeMap::new();
+    for word in words {
+        dictionary.entry(word.len()).or_default().push(word);
+    }
+    assert!(!dictionary.is_empty(), "Dictionary should not be empty");
+    dictionary
+});
+
+/// Mentions of users, roles and channels, custom emojis and timestamps, which all contain IDs.
+const MENTION_PATTERN: &str = concat!(
+    r"<(?:@[!&]?|#)\d+>",
+    r"|<a?:\w+:\d+>",
+    r"|<t:-?\d+(?::\w)?>",
+);
+
+pub static MENTION: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(MENTION_PATTERN).expect("Mention regex should be valid"));
+
+/// Parts of a message that keep their exact text:
+/// code fence markers (with language), mentions, channels, roles, custom emojis,
+/// timestamps and URLs (which are handled separately).
+/// Standard emojis are stored as Unicode and are kept like any other punctuation.
+static PRESERVED: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(
+        r"```[\w+-]*|{MENTION_PATTERN}|@everyone|@here|(?P<url>https?://\S+)"
+    ))
+    .expect("Preserved token regex should be valid")
+});
+
+/// Picks a dictionary word of the same length, or the closest shorter one.
+fn random_word(rng: &mut impl Rng, length: usize) -> &'static str {
+    let dictionary = &*DICTIONARY;
+    let words = dictionary
+        .range(..=length)
+        .next_back()
+        .or_else(|| dictionary.iter().next())
+        .map(|(_, words)| words)
+        .expect("Dictionary should not be empty");
+    words.choose(rng).expect("Word groups should not be empty")
+}
+
+/// Copies the capitalization of the original word onto the replacement.
+fn match_case(original: &str, replacement: &str) -> String {
+    let letters = original.chars().filter(|c| c.is_alphabetic()).count();
+    let uppercase = original.chars().filter(|c| c.is_uppercase()).count();
+    if letters > 1 && uppercase == letters {
+        return replacement.to_uppercase();
+    }
+    if original.starts_with(char::is_uppercase) {
+        let mut chars = replacement.chars();
+        return match chars.next() {
+            Some(first) => first.to_uppercase().chain(chars).collect(),
+            None => String::new(),
+        };
+    }
+    replacement.to_string()
+}
+
+fn random_digits(rng: &mut impl Rng, length: usize) -> String {
+    (0..length)
+        .map(|_| char::from(b'0' + rng.random_range(0..10u8)))
+        .collect()
+}
+
+/// Replaces every word with a random word of similar length and every number
+/// with random digits, keeping whitespace, punctuation and markdown as they are.
+fn scramble_words(rng: &mut impl Rng, text: &str, result: &mut String) {
+    let mut chars = text.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        if !c.is_alphanumeric() {
+            result.push(c);
+            continue;
+        }
+        let is_digit = c.is_ascii_digit();
+        let mut end = start + c.len_utf8();
+        while let Some(&(i, next)) = chars.peek() {
+            if !next.is_alphanumeric() || next.is_ascii_digit() != is_digit {
+                break;
+            }
+            end = i + next.len_utf8();
+            chars.next();
+        }
+        let word = &text[start..end];
+        let length = word.chars().count();
+        if is_digit {
+            result.push_str(&random_digits(rng, length));
+        } else {
+            result.push_str(&match_case(word, random_word(rng, length)));
+        }
+    }
+}
+
+/// Keeps the scheme and punctuation of a URL but scrambles hosts and path segments.
+fn scramble_url(rng: &mut impl Rng, url: &str, result: &mut String) {
+    let (scheme, rest) = url.split_once("://").unwrap_or(("https", url));
+    result.push_str(scheme);
+    result.push_str("://");
+    scramble_words(rng, rest, result);
+}
+
+/// Rewrites a message with random words while keeping its shape: line breaks,
+/// code fences, markdown, mentions, emojis and the structure of URLs.
+pub fn scramble(rng: &mut impl Rng, content: &str) -> String {
+    let mut result = String::with_capacity(content.len());
+    let mut position = 0;
+
+    for captures in PRESERVED.captures_iter(content) {
+        let token = captures.get(0).expect("Capture 0 always exists");
+        scramble_words(rng, &content[position..token.start()], &mut result);
+        if captures.name("url").is_some() {
+            scramble_url(rng, token.as_str(), &mut result);
+        } else {
+            result.push_str(token.as_str());
+        }
+        position = token.end();
+    }
+    scramble_words(rng, &content[position..], &mut result);
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn scrambles_text_between_colons() {
+        let rng = &mut StdRng::seed_from_u64(0);
+        let scrambled = scramble(rng, "login root:hunter2:x at 10:30:45");
+        assert!(!scrambled.contains("hunter2"), "{scrambled}");
+        assert!(!scrambled.contains(":30:"), "{scrambled}");
+        assert_eq!(scrambled.matches(':').count(), 4);
+    }
+
+    #[test]
+    fn keeps_mentions_and_custom_emojis() {
+        let rng = &mut StdRng::seed_from_u64(0);
+        let scrambled = scramble(rng, "hi <@123> <a:wave:456> 👋");
+        assert!(
+            scrambled.ends_with(" <@123> <a:wave:456> 👋"),
+            "{scrambled}"
+        );
+    }
+}
@@ -1,62 +1,53 @@
-use rand::random_range;
-
-const RAW_QUOTES_CONTENT: &[u8] = include_bytes!("shakespeare.txt");
-
-pub fn generate_shakespeare(length: usize) -> String {
-    let file = RAW_QUOTES_CONTENT;
-    let desired_quote_length = match length {
-        0..=100 => 100,
-        101..=300 => 300,
-        _ => 2000,
+use crate::corpus::Corpus;
+use rand::Rng;
+use std::collections::HashSet;
+use std::sync::LazyLock;
+
+pub const RAW_QUOTES_CONTENT: &[u8] = include_bytes!("shakespeare.txt");
+
+/// Parsed once at first use.
+static QUOTES: LazyLock<Corpus> = LazyLock::new(|| {
+    let text = str::from_utf8(RAW_QUOTES_CONTENT).expect("Shakespeare corpus should be UTF-8");
+    Corpus::from_entries(parse_quotes(text))
+        .expect("Shakespeare corpus should contain short quotes")
+});
+
+/// Speaker lines consist of an uppercase name followed by a dot, e.g. `KING LEAR.`
+fn is_speaker_line(line: &str) -> bool {
+    let Some(name) = line.strip_suffix('.') else {
+        return false;
     };
+    name.starts_with(|c: char| c.is_ascii_uppercase())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c == ' ' || c == '-' || c == '\'')
+}
 
-    let quotes_length = file.len();
-    let mut index = quotes_length; // Will be reset in first iteration
-
-    loop {
-        if index + 1 >= quotes_length {
-            index = random_range(0..quotes_length - 1);
-        }
+/// Every quote is the paragraph following a speaker line.
+fn parse_quotes(text: &str) -> Vec<String> {
+    let mut quotes = vec![];
+    let mut lines = text.lines().map(str::trim_end);
 
-        index += 1;
-        // Skip if in middle of line
-        if file.get(index) != Some(&b'\n') {
+    while let Some(line) = lines.next() {
+        if !is_speaker_line(line) {
             continue;
         }
-
-        while file.get(index) == Some(&b'\n') {
-            index += 1;
-        }
-
-        let mut result: String = String::new();
-
-        // Check each full line
-        loop {
-            index += 1;
-
-            if index >= quotes_length {
-                break;
-            }
-
-            // We want a line with only uppercase letters and a dot at the end
-            if !file[index].is_ascii_uppercase() && file.get(index) != Some(&b'.') {
-                break;
-            }
-
-            // If a dot is encountered, check if this is the end of the line
-            if file.get(index) == Some(&b'.') && file.get(index + 1) == Some(&b'\n') {
-                // Criteria were met. Now scan until next new line to obtain a quote
-                index += 1;
-                while !(file.get(index) == Some(&b'\n') && file.get(index + 1) == Some(&b'\n')) {
-                    result.push(file[index] as char);
-                    index += 1;
-                }
-                break;
-            }
-        }
-
-        if result.len() <= desired_quote_length && !result.is_empty() {
-            return result;
+        let quote: Vec<&str> = lines.by_ref().take_while(|line| !line.is_empty()).collect();
+        if !quote.is_empty() {
+            quotes.push(quote.join("\n"));
         }
     }
+    quotes
+}
+
+/// Picks quotes of similar length (in characters), avoiding the quotes in `used`.
+/// See [`Corpus::generate`].
+pub fn generate_shakespeare(
+    rng: &mut impl Rng,
+    length: usize,
+    content_limit: usize,
+    match_length: bool,
+    used: &mut HashSet<usize>,
+) -> String {
+    QUOTES.generate(rng, length, content_limit, match_length, used)
 }
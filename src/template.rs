@@ -0,0 +1,148 @@
+use crate::extract::{Channel, Message};
+use chrono::Utc;
+
+#[derive(Debug, Clone, Copy)]
+enum Placeholder {
+    /// The date the message was redacted on.
+    Date,
+    /// The date the message was originally sent on.
+    OriginalDate,
+    OriginalLength,
+    Channel,
+    MessageId,
+}
+
+const PLACEHOLDERS: &[(&str, Placeholder)] = &[
+    ("date", Placeholder::Date),
+    ("original_date", Placeholder::OriginalDate),
+    ("original_length", Placeholder::OriginalLength),
+    ("channel", Placeholder::Channel),
+    ("message_id", Placeholder::MessageId),
+];
+
+#[derive(Debug, Clone)]
+enum Part {
+    Literal(String),
+    Placeholder(Placeholder),
+}
+
+/// A replacement text like `redacted on {date}`.
+/// Literal braces can be written as `{{` and `}}`.
+#[derive(Debug, Clone)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut parts = vec![];
+        let mut literal = String::new();
+        let mut chars = source.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => {
+                                return Err(format!(
+                                    "Unclosed placeholder {{{name} in replacement text"
+                                ));
+                            }
+                        }
+                    }
+                    let placeholder = PLACEHOLDERS
+                        .iter()
+                        .find(|(n, _)| *n == name)
+                        .map(|(_, p)| *p)
+                        .ok_or_else(|| {
+                            let names: Vec<String> =
+                                PLACEHOLDERS.iter().map(|(n, _)| format!("{{{n}}}")).collect();
+                            format!(
+                                "Unknown placeholder {{{name}}} in replacement text (available: {})",
+                                names.join(", "),
+                            )
+                        })?;
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(Part::Placeholder(placeholder));
+                }
+                '}' => {
+                    return Err(
+                        "Unmatched `}` in replacement text (use `}}` for a literal brace)"
+                            .to_string(),
+                    );
+                }
+                _ => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+
+        Ok(Self { parts })
+    }
+
+    pub fn render(&self, channel: &Channel, message: &Message) -> String {
+        let mut result = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(text) => result.push_str(text),
+                Part::Placeholder(Placeholder::Date) => {
+                    result.push_str(&Utc::now().format("%Y-%m-%d").to_string());
+                }
+                Part::Placeholder(Placeholder::OriginalDate) => {
+                    result.push_str(&message.timestamp.format("%Y-%m-%d").to_string());
+                }
+                Part::Placeholder(Placeholder::OriginalLength) => {
+                    result.push_str(&message.content.chars().count().to_string());
+                }
+                Part::Placeholder(Placeholder::Channel) => match &channel.name {
+                    Some(name) => result.push_str(name),
+                    None => result.push_str(&channel.id.to_string()),
+                },
+                Part::Placeholder(Placeholder::MessageId) => {
+                    result.push_str(&message.id.to_string());
+                }
+            }
+        }
+        result
+    }
+
+    /// Makes sure the rendered text is a valid message content for every message.
+    pub fn validate(
+        &self,
+        channels: &[(Channel, Vec<Message>)],
+        content_limit: usize,
+    ) -> Result<(), String> {
+        for (channel, messages) in channels {
+            for message in messages {
+                let text = self.render(channel, message);
+                if text.trim().is_empty() {
+                    return Err("The replacement text must not be empty".to_string());
+                }
+                let length = text.chars().count();
+                if length > content_limit {
+                    return Err(format!(
+                        "The replacement text for message {} would be {length} characters long, \
+                        but Discord only allows {content_limit}",
+                        message.id,
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
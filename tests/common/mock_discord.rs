@@ -0,0 +1,322 @@
+use serde_json::{Value, json};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Instant;
+use tiny_http::{Header, Server};
+
+/// A request the mock server received.
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    /// The path without the API prefix, e.g. `/channels/1/messages/2`.
+    pub path: String,
+    pub authorization: Option<String>,
+    pub body: String,
+    pub received_at: Instant,
+}
+
+impl Request {
+    pub fn json(&self) -> Value {
+        serde_json::from_str(&self.body).expect("Request body should be JSON")
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MockResponse {
+    status: u16,
+    body: Option<Value>,
+    headers: Vec<(&'static str, String)>,
+}
+
+impl MockResponse {
+    pub fn json(status: u16, body: Value) -> Self {
+        Self {
+            status,
+            body: Some(body),
+            headers: vec![],
+        }
+    }
+
+    pub fn no_content() -> Self {
+        Self {
+            status: 204,
+            body: None,
+            headers: vec![],
+        }
+    }
+
+    /// A Discord error with a JSON error code, e.g. 10008 for an unknown message.
+    pub fn error_code(status: u16, code: u64, message: &str) -> Self {
+        Self::json(status, json!({ "message": message, "code": code }))
+    }
+
+    pub fn server_error(status: u16) -> Self {
+        Self::json(
+            status,
+            json!({ "message": "Internal Server Error", "code": 0 }),
+        )
+    }
+
+    /// A 429 for the route's bucket.
+    pub fn rate_limited(retry_after: f64) -> Self {
+        Self::json(
+            429,
+            json!({ "message": "You are being rate limited.", "retry_after": retry_after, "global": false }),
+        )
+        .with_bucket(0, retry_after)
+        .with_header("Retry-After", retry_after.ceil().to_string())
+        .with_header("X-RateLimit-Scope", "user".to_string())
+    }
+
+    /// A 429 for the global rate limit, which applies to all routes.
+    pub fn globally_rate_limited(retry_after: f64) -> Self {
+        Self::json(
+            429,
+            json!({ "message": "You are being rate limited.", "retry_after": retry_after, "global": true }),
+        )
+        .with_header("Retry-After", retry_after.ceil().to_string())
+        .with_header("X-RateLimit-Global", "true".to_string())
+        .with_header("X-RateLimit-Scope", "global".to_string())
+    }
+
+    /// A page of search results with the given channel ID, message ID and content.
+    pub fn search_results(messages: &[(u64, u64, &str)]) -> Self {
+        let messages: Vec<Value> = messages
+            .iter()
+            .map(|(channel_id, id, content)| {
+                json!([{
+                    "id": id.to_string(),
+                    "channel_id": channel_id.to_string(),
+                    "content": content,
+                    "timestamp": "2030-01-01T12:00:00.000000+00:00",
+                    "attachments": [],
+                    "hit": true,
+                }])
+            })
+            .collect();
+        Self::json(
+            200,
+            json!({ "total_results": messages.len(), "messages": messages }),
+        )
+    }
+
+    pub fn with_bucket(self, remaining: u32, reset_after: f64) -> Self {
+        self.with_header("X-RateLimit-Bucket", "mock-bucket".to_string())
+            .with_header("X-RateLimit-Limit", "5".to_string())
+            .with_header("X-RateLimit-Remaining", remaining.to_string())
+            .with_header("X-RateLimit-Reset-After", reset_after.to_string())
+    }
+
+    pub fn with_header(mut self, name: &'static str, value: String) -> Self {
+        self.headers.push((name, value));
+        self
+    }
+}
+
+/// Responses for all requests whose method matches and whose path starts with `path`.
+struct Override {
+    method: &'static str,
+    path: String,
+    /// Used up one by one; the last one is repeated if `repeat` is set.
+    responses: VecDeque<MockResponse>,
+    repeat: bool,
+}
+
+struct State {
+    user_id: u64,
+    requests: Vec<Request>,
+    overrides: Vec<Override>,
+}
+
+/// A local stand-in for Discord's API with the endpoints the tool uses:
+/// editing, deleting and searching messages, the current user and user profiles.
+///
+/// Every request is recorded. By default, all requests succeed;
+/// rate limits and errors are simulated with [`MockDiscord::respond`] and [`MockDiscord::respond_always`].
+pub struct MockDiscord {
+    server: Arc<Server>,
+    state: Arc<Mutex<State>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+pub const API_VERSION: u32 = 9;
+
+impl MockDiscord {
+    /// Starts the server on a free port. `user_id` is the account the token belongs to.
+    pub fn start(user_id: u64) -> Self {
+        let server = Arc::new(Server::http("127.0.0.1:0").expect("Mock server should start"));
+        let state = Arc::new(Mutex::new(State {
+            user_id,
+            requests: vec![],
+            overrides: vec![],
+        }));
+
+        let thread = {
+            let server = Arc::clone(&server);
+            let state = Arc::clone(&state);
+            std::thread::spawn(move || {
+                // `recv` fails once the server is unblocked on drop.
+                while let Ok(request) = server.recv() {
+                    handle(&state, request);
+                }
+            })
+        };
+
+        Self {
+            server,
+            state,
+            thread: Some(thread),
+        }
+    }
+
+    /// The value for `--api-base`.
+    pub fn api_base(&self) -> String {
+        let address = self
+            .server
+            .server_addr()
+            .to_ip()
+            .expect("Mock server should listen on TCP");
+        format!("http://{address}/api")
+    }
+
+    /// Answers the next matching requests with the given responses, in order.
+    pub fn respond(&self, method: &'static str, path: &str, responses: Vec<MockResponse>) {
+        self.add_override(method, path, responses, false);
+    }
+
+    /// Answers all matching requests with the given response.
+    pub fn respond_always(&self, method: &'static str, path: &str, response: MockResponse) {
+        self.add_override(method, path, vec![response], true);
+    }
+
+    fn add_override(
+        &self,
+        method: &'static str,
+        path: &str,
+        responses: Vec<MockResponse>,
+        repeat: bool,
+    ) {
+        self.state.lock().unwrap().overrides.push(Override {
+            method,
+            path: path.to_string(),
+            responses: responses.into(),
+            repeat,
+        });
+    }
+
+    pub fn requests(&self) -> Vec<Request> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// The recorded requests to edit or delete messages, leaving out profile lookups.
+    pub fn message_requests(&self) -> Vec<Request> {
+        self.requests()
+            .into_iter()
+            .filter(|request| request.path.starts_with("/channels/"))
+            .collect()
+    }
+}
+
+impl Drop for MockDiscord {
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn handle(state: &Mutex<State>, mut request: tiny_http::Request) {
+    let received_at = Instant::now();
+    let mut body = String::new();
+    let _ = request.as_reader().read_to_string(&mut body);
+    let prefix = format!("/api/v{API_VERSION}");
+    let path = request
+        .url()
+        .strip_prefix(&prefix)
+        .unwrap_or(request.url())
+        .to_string();
+    let recorded = Request {
+        method: request.method().to_string(),
+        path,
+        authorization: request
+            .headers()
+            .iter()
+            .find(|header| header.field.equiv("Authorization"))
+            .map(|header| header.value.to_string()),
+        body,
+        received_at,
+    };
+
+    let response = {
+        let mut state = state.lock().unwrap();
+        state.requests.push(recorded.clone());
+        overridden_response(&mut state, &recorded)
+            .unwrap_or_else(|| default_response(state.user_id, &recorded))
+    };
+
+    let status = response.status;
+    let mut headers: Vec<Header> = response
+        .headers
+        .iter()
+        .map(|(name, value)| Header::from_bytes(*name, value.as_bytes()).unwrap())
+        .collect();
+    let _ = match response.body {
+        Some(body) => {
+            headers.push(Header::from_bytes("Content-Type", "application/json").unwrap());
+            let mut reply =
+                tiny_http::Response::from_string(body.to_string()).with_status_code(status);
+            for header in headers {
+                reply.add_header(header);
+            }
+            request.respond(reply)
+        }
+        None => {
+            let mut reply = tiny_http::Response::empty(status);
+            for header in headers {
+                reply.add_header(header);
+            }
+            request.respond(reply)
+        }
+    };
+}
+
+fn overridden_response(state: &mut State, request: &Request) -> Option<MockResponse> {
+    let index = state.overrides.iter().position(|o| {
+        o.method == request.method && request.path.starts_with(&o.path) && !o.responses.is_empty()
+    })?;
+    let entry = &mut state.overrides[index];
+    if entry.repeat && entry.responses.len() == 1 {
+        return entry.responses.front().cloned();
+    }
+    entry.responses.pop_front()
+}
+
+fn default_response(user_id: u64, request: &Request) -> MockResponse {
+    let path = request.path.split('?').next().unwrap_or_default();
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    match (request.method.as_str(), segments.as_slice()) {
+        ("PATCH", ["channels", channel_id, "messages", message_id]) => {
+            let mut message = serde_json::from_str(&request.body).unwrap_or_else(|_| json!({}));
+            message["id"] = json!(message_id);
+            message["channel_id"] = json!(channel_id);
+            MockResponse::json(200, message).with_bucket(4, 1.0)
+        }
+        ("DELETE", ["channels", _, "messages", _]) => {
+            MockResponse::no_content().with_bucket(4, 1.0)
+        }
+        ("GET", ["guilds" | "channels", _, "messages", "search"]) => {
+            MockResponse::search_results(&[])
+        }
+        ("GET", ["users", "@me"]) => MockResponse::json(
+            200,
+            json!({ "id": user_id.to_string(), "username": "mock-user" }),
+        ),
+        ("GET", ["users", id, "profile"]) => MockResponse::json(
+            200,
+            json!({ "user": { "id": id, "global_name": format!("User {id}") } }),
+        ),
+        _ => MockResponse::error_code(404, 0, "404: Not Found"),
+    }
+}
@@ -0,0 +1,143 @@
+#![allow(dead_code)]
+
+pub mod mock_discord;
+
+use serde_json::json;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread::{JoinHandle, sleep};
+use std::time::{Duration, Instant};
+
+/// How long a run may take before it is considered hung.
+const RUN_TIMEOUT: Duration = Duration::from_secs(60);
+
+pub const USER_ID: u64 = 100;
+pub const TOKEN: &str = "mock-token";
+
+/// A synthetic Discord data export in a temporary directory, removed on drop.
+pub struct Export {
+    pub dir: PathBuf,
+}
+
+impl Export {
+    pub fn new(user_id: u64) -> Self {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "discord-mass-redact-test-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed),
+        ));
+        std::fs::create_dir_all(dir.join("account")).unwrap();
+        std::fs::create_dir_all(dir.join("messages")).unwrap();
+        std::fs::write(
+            dir.join("account").join("user.json"),
+            json!({ "id": user_id.to_string(), "username": "mock-user" }).to_string(),
+        )
+        .unwrap();
+        Self { dir }
+    }
+
+    /// Adds a DM channel with the given messages (ID and content).
+    pub fn dm(&self, channel_id: u64, messages: &[(u64, &str)]) -> &Self {
+        self.channel(
+            json!({ "id": channel_id.to_string(), "type": "DM", "recipients": [USER_ID.to_string(), "200"] }),
+            messages,
+        )
+    }
+
+    /// Adds a guild text channel with the given messages (ID and content).
+    pub fn guild_channel(&self, guild_id: u64, channel_id: u64, messages: &[(u64, &str)]) -> &Self {
+        self.channel(
+            json!({
+                "id": channel_id.to_string(),
+                "type": "GUILD_TEXT",
+                "name": format!("channel-{channel_id}"),
+                "guild": { "id": guild_id.to_string(), "name": format!("guild-{guild_id}") },
+            }),
+            messages,
+        )
+    }
+
+    fn channel(&self, channel: serde_json::Value, messages: &[(u64, &str)]) -> &Self {
+        let id = channel["id"].as_str().unwrap();
+        let dir = self.dir.join("messages").join(format!("c{id}"));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("channel.json"), channel.to_string()).unwrap();
+        let messages: Vec<_> = messages
+            .iter()
+            .map(|(id, content)| {
+                json!({
+                    "ID": id,
+                    "Timestamp": "2021-06-01 12:00:00",
+                    "Contents": content,
+                    "Attachments": "",
+                })
+            })
+            .collect();
+        std::fs::write(dir.join("messages.json"), json!(messages).to_string()).unwrap();
+        self
+    }
+
+    /// Creates an empty file in the export directory, e.g. for `--continuation-file`.
+    pub fn empty_file(&self, name: &str) -> PathBuf {
+        let path = self.dir.join(name);
+        std::fs::write(&path, "").unwrap();
+        path
+    }
+}
+
+impl Drop for Export {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+pub struct Run {
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// Runs the tool against the mock server, confirming the start prompt.
+pub fn run(api_base: &str, export_dir: &Path, args: &[&str]) -> Run {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_discord-mass-redact"))
+        .arg(TOKEN)
+        .arg(export_dir)
+        .args(args)
+        .args(["--api-base", api_base])
+        // Keep failing tests fast.
+        .args(["--retry-delay", "0.01", "--timeout", "10"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Binary should start");
+    child.stdin.take().unwrap().write_all(b"\n").unwrap();
+    let stdout = read_to_end(child.stdout.take().unwrap());
+    let stderr = read_to_end(child.stderr.take().unwrap());
+
+    let started = Instant::now();
+    while child.try_wait().unwrap().is_none() {
+        if started.elapsed() > RUN_TIMEOUT {
+            child.kill().unwrap();
+            panic!(
+                "The run did not finish within {RUN_TIMEOUT:?}. Output:\n{}",
+                stdout.join().unwrap()
+            );
+        }
+        sleep(Duration::from_millis(20));
+    }
+    Run {
+        stdout: stdout.join().unwrap(),
+        stderr: stderr.join().unwrap(),
+    }
+}
+
+fn read_to_end(mut pipe: impl Read + Send + 'static) -> JoinHandle<String> {
+    std::thread::spawn(move || {
+        let mut output = vec![];
+        let _ = pipe.read_to_end(&mut output);
+        String::from_utf8_lossy(&output).into_owned()
+    })
+}
@@ -0,0 +1,567 @@
+mod common;
+
+use common::mock_discord::{MockDiscord, MockResponse};
+use common::{Export, TOKEN, USER_ID, run};
+use std::time::Duration;
+
+fn message_ids(requests: &[common::mock_discord::Request], method: &str) -> Vec<String> {
+    requests
+        .iter()
+        .filter(|r| r.method == method)
+        .map(|r| r.path.rsplit('/').next().unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn deletes_all_messages_and_records_them() {
+    let discord = MockDiscord::start(USER_ID);
+    let export = Export::new(USER_ID);
+    export
+        .dm(1, &[(11, "first"), (12, "second")])
+        .guild_channel(5, 2, &[(21, "hello")]);
+    let continuation = export.empty_file("continuation.txt");
+
+    let output = run(
+        &discord.api_base(),
+        &export.dir,
+        &[
+            "delete",
+            "--continuation-file",
+            continuation.to_str().unwrap(),
+        ],
+    );
+
+    assert!(output.stdout.contains("Logged in as"), "{}", output.stdout);
+    assert!(output.stdout.contains("Done!"), "{}", output.stdout);
+    let requests = discord.message_requests();
+    // Round-robin across channels, sorted by channel ID.
+    assert_eq!(message_ids(&requests, "DELETE"), ["11", "21", "12"]);
+    assert!(
+        requests
+            .iter()
+            .all(|r| r.authorization.as_deref() == Some(TOKEN))
+    );
+
+    let mut finished: Vec<String> = std::fs::read_to_string(&continuation)
+        .unwrap()
+        .lines()
+        .map(str::to_string)
+        .collect();
+    finished.sort();
+    assert_eq!(finished, ["11", "12", "21"]);
+
+    // A second run has nothing left to do.
+    let output = run(
+        &discord.api_base(),
+        &export.dir,
+        &[
+            "delete",
+            "--continuation-file",
+            continuation.to_str().unwrap(),
+        ],
+    );
+    assert!(
+        output.stdout.contains("Got 0 messages"),
+        "{}",
+        output.stdout
+    );
+    assert_eq!(discord.message_requests().len(), 3);
+}
+
+#[test]
+fn seeded_edits_are_reproducible() {
+    let export = Export::new(USER_ID);
+    export.dm(
+        1,
+        &[
+            (11, "a message that should be replaced"),
+            (12, "another one"),
+        ],
+    );
+
+    let contents = || {
+        let discord = MockDiscord::start(USER_ID);
+        run(
+            &discord.api_base(),
+            &export.dir,
+            &["shakespeare", "--seed", "42"],
+        );
+        discord
+            .message_requests()
+            .iter()
+            .map(|r| {
+                assert_eq!(r.method, "PATCH");
+                assert_eq!(r.json()["attachments"], serde_json::json!([]));
+                r.json()["content"].as_str().unwrap().to_string()
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let first = contents();
+    assert_eq!(first.len(), 2);
+    assert!(first.iter().all(|content| !content.is_empty()));
+    assert_eq!(first, contents());
+}
+
+#[test]
+fn retries_after_route_rate_limit() {
+    let discord = MockDiscord::start(USER_ID);
+    let export = Export::new(USER_ID);
+    export.dm(1, &[(11, "first"), (12, "second")]);
+    discord.respond(
+        "DELETE",
+        "/channels/1/messages/11",
+        vec![MockResponse::rate_limited(0.2)],
+    );
+
+    let output = run(&discord.api_base(), &export.dir, &["delete"]);
+
+    assert!(
+        !output.stdout.contains("could not be redacted"),
+        "{}",
+        output.stdout
+    );
+    assert_eq!(
+        message_ids(&discord.message_requests(), "DELETE"),
+        ["11", "11", "12"]
+    );
+}
+
+#[test]
+fn global_rate_limit_pauses_all_routes() {
+    let discord = MockDiscord::start(USER_ID);
+    let export = Export::new(USER_ID);
+    export
+        .dm(1, &[(11, "first")])
+        .dm(2, &[(21, "a"), (22, "b"), (23, "c")]);
+    discord.respond(
+        "DELETE",
+        "/channels/1/messages/11",
+        vec![MockResponse::globally_rate_limited(0.5)],
+    );
+
+    let output = run(
+        &discord.api_base(),
+        &export.dir,
+        &["delete", "--workers", "2"],
+    );
+
+    assert!(
+        !output.stdout.contains("could not be redacted"),
+        "{}",
+        output.stdout
+    );
+    let requests = discord.message_requests();
+    let mut deleted = message_ids(&requests, "DELETE");
+    deleted.sort();
+    assert_eq!(deleted, ["11", "11", "21", "22", "23"]);
+
+    // Channel 2 may have had one request in flight when the limit was hit,
+    // but every later request waits for the global reset.
+    let limited_at = requests
+        .iter()
+        .find(|r| r.path == "/channels/1/messages/11")
+        .unwrap()
+        .received_at;
+    let reset_at = limited_at + Duration::from_millis(500);
+    let during_limit = requests
+        .iter()
+        .filter(|r| r.path.starts_with("/channels/2/"))
+        .filter(|r| r.received_at > limited_at && r.received_at < reset_at)
+        .count();
+    assert!(during_limit <= 1, "{requests:?}");
+}
+
+#[test]
+fn retries_server_errors_with_backoff() {
+    let discord = MockDiscord::start(USER_ID);
+    let export = Export::new(USER_ID);
+    export.dm(1, &[(11, "flaky"), (12, "broken")]);
+    discord.respond(
+        "DELETE",
+        "/channels/1/messages/11",
+        vec![
+            MockResponse::server_error(502),
+            MockResponse::server_error(503),
+        ],
+    );
+    discord.respond_always(
+        "DELETE",
+        "/channels/1/messages/12",
+        MockResponse::server_error(500),
+    );
+
+    let output = run(
+        &discord.api_base(),
+        &export.dir,
+        &["delete", "--max-retries", "2"],
+    );
+
+    let ids = message_ids(&discord.message_requests(), "DELETE");
+    assert_eq!(ids.iter().filter(|id| *id == "11").count(), 3);
+    assert_eq!(ids.iter().filter(|id| *id == "12").count(), 3);
+    let (rejected, exhausted) = output
+        .stdout
+        .split_once("server or network errors")
+        .unwrap_or((&output.stdout, ""));
+    assert!(
+        !rejected.contains("could not be redacted"),
+        "{}",
+        output.stdout
+    );
+    assert!(exhausted.contains("12 - \"broken\""), "{}", output.stdout);
+    assert!(!exhausted.contains("11 - "), "{}", output.stdout);
+}
+
+#[test]
+fn already_deleted_messages_count_as_done() {
+    let discord = MockDiscord::start(USER_ID);
+    let export = Export::new(USER_ID);
+    export.dm(1, &[(11, "gone"), (12, "forbidden")]);
+    let continuation = export.empty_file("continuation.txt");
+    discord.respond(
+        "DELETE",
+        "/channels/1/messages/11",
+        vec![MockResponse::error_code(404, 10008, "Unknown Message")],
+    );
+    discord.respond(
+        "DELETE",
+        "/channels/1/messages/12",
+        vec![MockResponse::error_code(
+            400,
+            50021,
+            "Cannot execute action on a system message",
+        )],
+    );
+
+    let output = run(
+        &discord.api_base(),
+        &export.dir,
+        &[
+            "delete",
+            "--continuation-file",
+            continuation.to_str().unwrap(),
+        ],
+    );
+
+    assert_eq!(std::fs::read_to_string(&continuation).unwrap().trim(), "11");
+    assert!(
+        output.stdout.contains("12 - \"forbidden\""),
+        "{}",
+        output.stdout
+    );
+    assert!(
+        !output.stdout.contains("11 - \"gone\""),
+        "{}",
+        output.stdout
+    );
+}
+
+#[test]
+fn skips_unreachable_channels() {
+    let discord = MockDiscord::start(USER_ID);
+    let export = Export::new(USER_ID);
+    export
+        .guild_channel(5, 1, &[(11, "a"), (12, "b"), (13, "c")])
+        .dm(2, &[(21, "reachable")]);
+    discord.respond_always(
+        "PATCH",
+        "/channels/1/",
+        MockResponse::error_code(403, 50001, "Missing Access"),
+    );
+
+    let output = run(
+        &discord.api_base(),
+        &export.dir,
+        &["text", "--replacement-text", "[redacted]"],
+    );
+
+    let requests = discord.message_requests();
+    assert_eq!(message_ids(&requests, "PATCH"), ["11", "21"]);
+    assert_eq!(requests[1].json()["content"], "[redacted]");
+    assert!(
+        output
+            .stdout
+            .contains("\"channel-1\" (1): 3 messages unreachable"),
+        "{}",
+        output.stdout
+    );
+}
+
+#[test]
+fn skips_unreachable_channels_with_several_workers() {
+    let discord = MockDiscord::start(USER_ID);
+    let export = Export::new(USER_ID);
+    export
+        .guild_channel(5, 1, &[(11, "a"), (12, "b"), (13, "c")])
+        .dm(2, &[(21, "reachable")]);
+    // The second worker finishes channel 2 and waits for channel 1 while it is rate limited.
+    discord.respond(
+        "DELETE",
+        "/channels/1/",
+        vec![MockResponse::rate_limited(0.3)],
+    );
+    discord.respond_always(
+        "DELETE",
+        "/channels/1/",
+        MockResponse::error_code(403, 50001, "Missing Access"),
+    );
+
+    let output = run(
+        &discord.api_base(),
+        &export.dir,
+        &["delete", "--workers", "2"],
+    );
+
+    let mut deleted = message_ids(&discord.message_requests(), "DELETE");
+    deleted.sort();
+    assert_eq!(deleted, ["11", "11", "21"]);
+    assert!(
+        output
+            .stdout
+            .contains("\"channel-1\" (1): 3 messages unreachable"),
+        "{}",
+        output.stdout
+    );
+}
+
+#[test]
+fn refuses_tokens_of_other_accounts() {
+    let discord = MockDiscord::start(USER_ID + 1);
+    let export = Export::new(USER_ID);
+    export.dm(1, &[(11, "first")]);
+
+    let output = run(&discord.api_base(), &export.dir, &["delete"]);
+    assert!(
+        output.stderr.contains("--allow-account-mismatch"),
+        "{}",
+        output.stderr
+    );
+    assert!(discord.message_requests().is_empty());
+
+    run(
+        &discord.api_base(),
+        &export.dir,
+        &["delete", "--allow-account-mismatch"],
+    );
+    assert_eq!(discord.message_requests().len(), 1);
+}
+
+#[test]
+fn refuses_invalid_tokens() {
+    let discord = MockDiscord::start(USER_ID);
+    let export = Export::new(USER_ID);
+    export.dm(1, &[(11, "first")]);
+    discord.respond_always(
+        "GET",
+        "/users/@me",
+        MockResponse::error_code(401, 0, "401: Unauthorized"),
+    );
+
+    let output = run(&discord.api_base(), &export.dir, &["delete"]);
+    assert!(
+        output.stderr.contains("invalid or expired"),
+        "{}",
+        output.stderr
+    );
+    assert!(discord.message_requests().is_empty());
+}
+
+#[test]
+fn discovers_messages_newer_than_the_export() {
+    let discord = MockDiscord::start(USER_ID);
+    let export = Export::new(USER_ID);
+    export
+        .guild_channel(5, 2, &[(21, "old")])
+        .dm(1, &[(11, "old dm")]);
+    discord.respond(
+        "GET",
+        "/guilds/5/messages/search",
+        vec![MockResponse::search_results(&[
+            (2, 30, "newer"),
+            (3, 40, "in a new channel"),
+            (2, 21, "old"),
+        ])],
+    );
+    discord.respond(
+        "GET",
+        "/channels/1/messages/search",
+        vec![MockResponse::search_results(&[(1, 50, "newer dm")])],
+    );
+
+    let output = run(
+        &discord.api_base(),
+        &export.dir,
+        &["delete", "--discover-new"],
+    );
+
+    assert!(
+        output.stdout.contains("Discovered 3 messages"),
+        "{}",
+        output.stdout
+    );
+    let searches: Vec<_> = discord
+        .requests()
+        .into_iter()
+        .filter(|r| r.path.contains("/messages/search"))
+        .collect();
+    assert!(!searches.is_empty());
+    assert!(
+        searches
+            .iter()
+            .all(|r| r.path.contains(&format!("author_id={USER_ID}")))
+    );
+    assert!(searches.iter().all(|r| r.path.contains("min_id=")));
+    assert!(searches.iter().any(|r| r.path.contains("min_id=21")));
+
+    let mut deleted = message_ids(&discord.message_requests(), "DELETE");
+    deleted.sort();
+    assert_eq!(deleted, ["11", "21", "30", "40", "50"]);
+}
+
+#[test]
+fn leaves_messages_without_matches_untouched() {
+    let discord = MockDiscord::start(USER_ID);
+    let export = Export::new(USER_ID);
+    export.dm(1, &[(11, "mail me at a@example.com"), (12, "nothing here")]);
+
+    run(
+        &discord.api_base(),
+        &export.dir,
+        &[
+            "overwrite-delete",
+            "--overwrite-with",
+            "mask",
+            "--mask-pii",
+            "email",
+        ],
+    );
+
+    let requests = discord.message_requests();
+    assert_eq!(message_ids(&requests, "PATCH"), ["11"]);
+    assert_eq!(requests[0].json()["content"], "mail me at █████");
+    assert_eq!(message_ids(&requests, "DELETE"), ["11"]);
+}
+
+#[test]
+fn rejects_masks_over_the_content_limit() {
+    let discord = MockDiscord::start(USER_ID);
+    let export = Export::new(USER_ID);
+    let content = "x ".repeat(1000);
+    export.dm(1, &[(11, &content)]);
+
+    let output = run(
+        &discord.api_base(),
+        &export.dir,
+        &["mask", "--mask-pattern", "x", "--mask-text", "[removed]"],
+    );
+
+    assert!(discord.message_requests().is_empty());
+    assert!(
+        output.stdout.contains("could not be redacted"),
+        "{}",
+        output.stdout
+    );
+}
+
+#[test]
+fn rejects_final_states_without_random_overwrites() {
+    let discord = MockDiscord::start(USER_ID);
+    let export = Export::new(USER_ID);
+    export.dm(1, &[(11, "secret")]);
+
+    let output = run(
+        &discord.api_base(),
+        &export.dir,
+        &[
+            "encrypt",
+            "--passphrase",
+            "correct horse",
+            "--final-state",
+            "delete",
+        ],
+    );
+
+    assert!(
+        output.stderr.contains("final state can only be changed"),
+        "{}",
+        output.stderr
+    );
+    assert!(discord.message_requests().is_empty());
+}
+
+#[test]
+fn retries_searches_only_while_discord_is_indexing() {
+    let discord = MockDiscord::start(USER_ID);
+    let export = Export::new(USER_ID);
+    export.dm(1, &[(11, "old dm")]).dm(2, &[(21, "other dm")]);
+    discord.respond(
+        "GET",
+        "/channels/1/messages/search",
+        vec![
+            MockResponse::json(202, serde_json::json!({ "retry_after": 0.01 })),
+            MockResponse::search_results(&[(1, 50, "newer dm")]),
+        ],
+    );
+    discord.respond_always(
+        "GET",
+        "/channels/2/messages/search",
+        MockResponse::json(200, serde_json::json!({})),
+    );
+
+    let output = run(
+        &discord.api_base(),
+        &export.dir,
+        &["delete", "--discover-new"],
+    );
+
+    assert!(
+        output
+            .stdout
+            .contains("Search response does not contain messages"),
+        "{}",
+        output.stdout
+    );
+    let searches = |channel_id: u64| {
+        discord
+            .requests()
+            .iter()
+            .filter(|r| {
+                r.path
+                    .starts_with(&format!("/channels/{channel_id}/messages/search"))
+            })
+            .count()
+    };
+    // The second search of channel 1 finds nothing newer than message 50.
+    assert_eq!(searches(1), 3);
+    assert_eq!(searches(2), 1);
+    let mut deleted = message_ids(&discord.message_requests(), "DELETE");
+    deleted.sort();
+    assert_eq!(deleted, ["11", "21", "50"]);
+}
+
+#[test]
+fn resumes_between_overwrite_and_delete() {
+    let discord = MockDiscord::start(USER_ID);
+    let export = Export::new(USER_ID);
+    export.dm(1, &[(11, "already overwritten")]);
+    let continuation = export.empty_file("continuation.txt");
+    std::fs::write(&continuation, "11 1\n").unwrap();
+
+    run(
+        &discord.api_base(),
+        &export.dir,
+        &[
+            "overwrite-delete",
+            "--continuation-file",
+            continuation.to_str().unwrap(),
+        ],
+    );
+
+    let requests = discord.message_requests();
+    assert_eq!(requests.len(), 1, "{requests:?}");
+    assert_eq!(requests[0].method, "DELETE");
+    assert_eq!(requests[0].path, "/channels/1/messages/11");
+    let content = std::fs::read_to_string(&continuation).unwrap();
+    assert_eq!(content.lines().last(), Some("11"));
+}